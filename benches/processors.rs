@@ -0,0 +1,62 @@
+//! Compare the L-System processors on a few growing states.
+//! Run with 'cargo bench'.
+
+extern crate time;
+
+extern crate rustlsystem;
+
+use rustlsystem::interpret::TurtleCommand;
+use rustlsystem::process::{LProcessor, SimpleProcessor, ChunksProcessor, InPlaceProcessor};
+use rustlsystem::rules::HashMapRules;
+use rustlsystem::state::{LSystem, new_rules_value};
+
+/// Number of runs per measurement, the best one being kept.
+const RUNS: usize = 5;
+
+/// Iterate the given L-System up to the given iteration with the given
+/// processor, and return the duration of the last iteration in seconds.
+fn measure(processor: &mut LProcessor<char>, lsystem: &LSystem<char>, iterations: u64) -> f64 {
+    let mut lsystem = lsystem.clone();
+    while lsystem.iteration() + 1 < iterations {
+        lsystem = processor.iterate(&lsystem).unwrap();
+    }
+    let mut best = f64::MAX;
+    for _ in 0..RUNS {
+        let start = time::precise_time_s();
+        let next = processor.iterate(&lsystem).unwrap();
+        best = best.min(time::precise_time_s() - start);
+        assert_eq!(next.iteration(), iterations);
+    }
+    best
+}
+
+fn main() {
+    let mut algae = HashMapRules::new();
+    algae.set_str('A', "AB", TurtleCommand::None);
+    algae.set_str('B', "A", TurtleCommand::None);
+    let algae = LSystem::new_with_char("A", new_rules_value(algae));
+
+    let mut koch = HashMapRules::new();
+    koch.set_str('F', "F+F-F-F+F", TurtleCommand::AdvanceBy(1.0));
+    koch.set_interpretation('+', TurtleCommand::RotateBy(90.0));
+    koch.set_interpretation('-', TurtleCommand::RotateBy(-90.0));
+    let koch = LSystem::new_with_char("F", new_rules_value(koch));
+
+    let cores = 4;
+    let mut processors: Vec<(&str, Box<LProcessor<char>>)> =
+        vec![("SimpleProcessor", Box::new(SimpleProcessor)),
+             ("ChunksProcessor", Box::new(ChunksProcessor::new(cores, 100_000).unwrap())),
+             ("ChunksProcessor auto", Box::new(ChunksProcessor::new_auto(cores).unwrap())),
+             ("InPlaceProcessor", Box::new(InPlaceProcessor::new(cores, 50_000).unwrap()))];
+
+    for &(name, lsystem, iterations) in &[("algae", &algae, 30), ("koch", &koch, 8)] {
+        for &mut (processor_name, ref mut processor) in &mut processors {
+            let seconds = measure(&mut **processor, lsystem, iterations);
+            println!("{:<8} iteration {:<4}{:<24}{:>10.3} ms",
+                     name,
+                     iterations,
+                     processor_name,
+                     seconds * 1000.0);
+        }
+    }
+}
@@ -1,71 +1,113 @@
 use std::cell::RefCell;
 use std::sync::mpsc::{Sender, Receiver};
+use std::thread::JoinHandle;
 
 use time;
 
 use rustlsystem::*;
-use rustlsystem::rules::HashMapRules;
-use rustlsystem::interpret::TurtleCommand::*;
+use rustlsystem::grammar::Grammar;
+use rustlsystem::handle::LSystemHandle;
+use rustlsystem::turtle::{Segment, Turtle, TurtleConfig};
+
+/// The number of turtle commands per interpretation batch received from the
+/// worker.
+const INTERPRETATION_BATCH: usize = 100_000;
 
 /// The application for viewing L-Systems.
 pub struct Application;
 
 impl Application {
-    pub fn run() {
-        use rustlsystem::view::MessageFromViewer::*;
+    /// Run the viewer on the given grammar for the given number of iterations.
+    pub fn run(grammar: &Grammar, iterations: u64) {
+        // worker test
+        let mut handle = Self::handle();
 
-        // L-System definition
-        let lsystem_axiom: Vec<char> = "A".chars().collect();
-        let mut lsystem_rules = HashMapRules::new();
-        lsystem_rules.set_str('A', "+B-A-B+", AdvanceBy(10f32));
-        lsystem_rules.set_str('B', "−A+B+A−", AdvanceBy(15f32));
-        lsystem_rules.set_str('+', "+", RotateBy(60f32));
-        lsystem_rules.set_str('-', "-", RotateBy(-60f32));
+        let t_start = time::now();
+        match handle.load(grammar.axiom.clone(), Box::new(grammar.rules.clone())) {
+            Ok(()) => println!("L-System loaded after {}s", time::now() - t_start),
+            Err(why) => println!("error: {}", why),
+        }
+        for _ in 0..iterations {
+            let t_start = time::now();
+            match handle.iterate() {
+                Ok((n, len)) => {
+                    println!("generation {} ({} symbols) derived after {}s",
+                             n,
+                             len,
+                             time::now() - t_start)
+                }
+                Err(why) => println!("error: {}", why),
+            }
+        }
+        if let Err(why) = handle.shutdown() {
+            println!("error: {}", why);
+        }
+    }
 
-        // worker test
+    /// Load the given grammar into the worker, iterate it the given number of
+    /// times and return the segments drawn by the turtle.
+    pub fn derive(handle: &mut LSystemHandle<char>,
+                  grammar: &Grammar,
+                  iterations: u64)
+                  -> Result<Vec<Segment>, String> {
+        try!(handle.load(grammar.axiom.clone(), Box::new(grammar.rules.clone())));
+        for _ in 0..iterations {
+            try!(handle.iterate());
+        }
+        // the segments are drawn while the state is interpreted
+        let mut turtle = Turtle::new(TurtleConfig::default());
+        let mut segments = Vec::new();
+        for batch in try!(handle.interpret_stream(INTERPRETATION_BATCH)) {
+            segments.extend(try!(batch).iter().filter_map(|c| turtle.apply(c)));
+        }
+        Ok(segments)
+    }
+
+    /// Load the given grammar into the worker and return the segments drawn by
+    /// the turtle from the axiom to the given iteration, with the given number
+    /// of growth steps per generation (1 for the generations only).
+    pub fn derive_growth(handle: &mut LSystemHandle<char>,
+                         grammar: &Grammar,
+                         iterations: u64,
+                         steps: u32)
+                         -> Result<Vec<Vec<Segment>>, String> {
+        let mut turtle = Turtle::new(TurtleConfig::default());
+        try!(handle.load(grammar.axiom.clone(), Box::new(grammar.rules.clone())));
+        let mut frames = Vec::new();
+        for _ in 0..iterations {
+            frames.push(turtle.execute(&try!(handle.interpret())));
+            for step in 1..steps {
+                let t = step as f32 / steps as f32;
+                frames.push(turtle.execute(&try!(handle.interpret_growth(t))));
+            }
+            try!(handle.iterate());
+        }
+        frames.push(turtle.execute(&try!(handle.interpret())));
+        Ok(frames)
+    }
+
+    /// Create the processor and interpreter used by all the viewer modes.
+    fn worker_parts() -> (Box<process::LProcessor<char> + Send>,
+                          Box<interpret::LInterpreter<char> + Send>) {
         let processor: Box<process::LProcessor<char> + Send> =
             Box::new(process::ChunksProcessor::new(4, 100_000).ok().unwrap());
         let interpreter: Box<interpret::LInterpreter<char> + Send> =
             Box::new(interpret::SimpleInterpreter);
-        let (tx, rx) = view::start_worker(RefCell::new(processor), RefCell::new(interpreter));
-
-        Self::command_and_wait(&tx, &rx, Iterate, true);
-        Self::command_and_wait(&tx,
-                               &rx,
-                               LoadLSystem(lsystem_axiom, Box::new(lsystem_rules)),
-                               true);
-        for _ in 0..10 {
-            Self::command_and_wait(&tx, &rx, Iterate, true);
-        }
-        Self::command_and_wait(&tx, &rx, Terminate, true);
+        (processor, interpreter)
     }
 
-    /// Send a command to the worker thread and return the associated response or error.
-    /// NB : asynchronous waiting should be preferred for long operations (e.g. iteration).
-    fn command_and_wait<S: Clone + Eq>(tx: &Sender<view::MessageFromViewer<S>>,
-                                       rx: &Receiver<view::MessageToViewer>,
-                                       msg: view::MessageFromViewer<S>,
-                                       print_time: bool)
-                                       -> view::MessageToViewer {
-        let error_type = view::MessageToViewer::Error(String::new());
-        let response_type = view::MessageToViewer::from_command(&msg);
-
-        let t_start = time::now();
-        tx.send(msg).unwrap();
+    /// Start the worker thread used by the viewer modes, and return its handle.
+    pub fn handle() -> LSystemHandle<char> {
+        let (processor, interpreter) = Self::worker_parts();
+        LSystemHandle::new(processor, interpreter)
+    }
 
-        loop {
-            match rx.recv() {
-                Ok(response) => {
-                    println!("worker thread sent : {:?}", response); // test
-                    if response.same_type(&response_type) || response.same_type(&error_type) {
-                        if print_time {
-                            println!("> response received after {}s", time::now() - t_start);
-                        }
-                        return response;
-                    }
-                }
-                _ => (),
-            }
-        }
+    /// Start the worker thread used by the event-driven viewer modes, and
+    /// return its channels and its handle (see 'view::shutdown_worker').
+    pub fn start_worker() -> (Sender<view::Request<char>>,
+                              Receiver<view::Response>,
+                              JoinHandle<()>) {
+        let (processor, interpreter) = Self::worker_parts();
+        view::spawn_worker(RefCell::new(processor), RefCell::new(interpreter))
     }
 }
@@ -3,8 +3,272 @@ extern crate time;
 extern crate rustlsystem;
 
 mod app;
+mod repl;
+mod tui;
+mod watch;
 use app::Application;
 
+use std::env;
+use std::io;
+use std::path::Path;
+use std::process;
+
+use rustlsystem::grammar::Grammar;
+use rustlsystem::presets::{self, DEFAULT_STEP};
+use rustlsystem::http::RenderServer;
+use rustlsystem::protocol;
+use rustlsystem::render::{self, animation, CanvasMode};
+use rustlsystem::view;
+
+const USAGE: &'static str = "Usage: rustlsystem-viewer [options]
+
+Options:
+    --preset NAME       view the given preset (default: sierpinski-arrowhead)
+    --grammar FILE      view the grammar defined in the given file
+    --iterations N      number of iterations (default: the grammar's recommended one)
+    --list-presets      list the available presets and exit
+    --tui               start the interactive terminal viewer
+    --ascii             draw with ASCII instead of braille characters in the terminal viewer
+    --repl              start the interactive shell
+    --serve             drive the worker with JSON lines on stdin, answered on stdout (see the
+                        'protocol' module)
+    --output FILE       render the L-System to an SVG or PNG image and exit
+    --size WxH          size of the rendered image (default: 800x800)
+    --watch             with --grammar and --output, render again each time the grammar file changes
+    --animate FILE      render every generation up to the iterations number, as an animated SVG
+                        or as a sequence of PNG frames (FILE_000.png, FILE_001.png...)
+    --frame-duration S  duration of each frame of an animated SVG, in seconds (default: 0.5)
+    --normalize         scale the animation frames so that the figure keeps its size
+    --growth-steps N    number of animation frames per generation, each symbol growing
+                        smoothly into its production between them (default: 1)
+    --http PORT         serve renderings on http://127.0.0.1:PORT (see the 'http' module)
+    --help              print this message and exit";
+
+/// Command line options of the viewer.
+struct Options {
+    grammar: Grammar,
+    grammar_path: Option<String>,
+    iterations: Option<u64>,
+    list_presets: bool,
+    tui: bool,
+    canvas_mode: CanvasMode,
+    repl: bool,
+    serve: bool,
+    http_port: Option<u16>,
+    output: Option<String>,
+    size: (u32, u32),
+    watch: bool,
+    animation: Option<String>,
+    frame_duration: f32,
+    normalize: bool,
+    growth_steps: u32,
+}
+
+impl Options {
+    /// Parse the command line arguments (without the program name).
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options {
+            grammar: Grammar::from_preset(presets::find("sierpinski-arrowhead").unwrap(),
+                                          DEFAULT_STEP),
+            grammar_path: None,
+            iterations: None,
+            list_presets: false,
+            tui: false,
+            canvas_mode: CanvasMode::Braille,
+            repl: false,
+            serve: false,
+            http_port: None,
+            output: None,
+            size: (800, 800),
+            watch: false,
+            animation: None,
+            frame_duration: 0.5,
+            normalize: false,
+            growth_steps: 1,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match &arg[..] {
+                "--preset" => {
+                    let name = try!(args.next().ok_or(format!("missing preset name")));
+                    let preset = try!(presets::find(name)
+                        .ok_or(format!("unknown preset '{}'", name)));
+                    options.grammar = Grammar::from_preset(preset, DEFAULT_STEP);
+                }
+                "--grammar" => {
+                    let path = try!(args.next().ok_or(format!("missing grammar file")));
+                    options.grammar = try!(Grammar::load(Path::new(path)));
+                    options.grammar_path = Some(path.clone());
+                }
+                "--iterations" => {
+                    let n = try!(args.next().ok_or(format!("missing iterations number")));
+                    options.iterations = Some(try!(n.parse()
+                        .map_err(|_| format!("invalid iterations number '{}'", n))));
+                }
+                "--list-presets" => options.list_presets = true,
+                "--tui" => options.tui = true,
+                "--ascii" => options.canvas_mode = CanvasMode::Ascii,
+                "--repl" => options.repl = true,
+                "--serve" => options.serve = true,
+                "--http" => {
+                    let port = try!(args.next().ok_or(format!("missing port number")));
+                    options.http_port = Some(try!(port.parse()
+                        .map_err(|_| format!("invalid port number '{}'", port))));
+                }
+                "--output" => {
+                    let path = try!(args.next().ok_or(format!("missing output file")));
+                    options.output = Some(path.clone());
+                }
+                "--size" => {
+                    let size = try!(args.next().ok_or(format!("missing image size")));
+                    let mut values = size.split('x').map(|v| v.parse());
+                    options.size = match (values.next(), values.next(), values.next()) {
+                        (Some(Ok(width)), Some(Ok(height)), None) if width > 0 && height > 0 => {
+                            (width, height)
+                        }
+                        _ => return Err(format!("invalid image size '{}'", size)),
+                    };
+                }
+                "--watch" => options.watch = true,
+                "--animate" => {
+                    let path = try!(args.next().ok_or(format!("missing animation file")));
+                    options.animation = Some(path.clone());
+                }
+                "--frame-duration" => {
+                    let duration = try!(args.next().ok_or(format!("missing frame duration")));
+                    options.frame_duration = match duration.parse() {
+                        Ok(duration) if duration > 0.0 => duration,
+                        _ => return Err(format!("invalid frame duration '{}'", duration)),
+                    };
+                }
+                "--normalize" => options.normalize = true,
+                "--growth-steps" => {
+                    let steps = try!(args.next().ok_or(format!("missing growth steps number")));
+                    options.growth_steps = match steps.parse() {
+                        Ok(steps) if steps > 0 => steps,
+                        _ => return Err(format!("invalid growth steps number '{}'", steps)),
+                    };
+                }
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+
+        if options.watch && (options.grammar_path.is_none() || options.output.is_none()) {
+            return Err(format!("--watch requires --grammar and --output"));
+        }
+        Ok(options)
+    }
+}
+
 fn main() {
-    Application::run();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let options = match Options::parse(&args) {
+        Ok(options) => options,
+        Err(why) => {
+            println!("error: {}\n\n{}", why, USAGE);
+            process::exit(1);
+        }
+    };
+
+    if options.list_presets {
+        for preset in presets::PRESETS {
+            println!("{:<24}{} ({} iterations, {} degrees)",
+                     preset.name,
+                     preset.description,
+                     preset.iterations,
+                     preset.angle);
+        }
+        return;
+    }
+
+    let iterations = options.iterations.or(options.grammar.iterations).unwrap_or(0);
+    if options.watch {
+        let grammar_path = options.grammar_path.unwrap();
+        let output = options.output.unwrap();
+        if let Err(why) = watch::run(Path::new(&grammar_path),
+                                     options.iterations,
+                                     Path::new(&output),
+                                     options.size) {
+            println!("error: {}", why);
+            process::exit(1);
+        }
+    } else if let Some(ref path) = options.animation {
+        let mut handle = Application::handle();
+        let result = Application::derive_growth(&mut handle,
+                                                &options.grammar,
+                                                iterations,
+                                                options.growth_steps)
+            .and_then(|mut frames| {
+                if options.normalize {
+                    animation::normalize(&mut frames);
+                }
+                animation::save_animation(Path::new(path),
+                                          &frames,
+                                          options.size.0,
+                                          options.size.1,
+                                          options.frame_duration)
+            });
+        let result = handle.shutdown().and(result);
+        match result {
+            Ok(files) => {
+                println!("{} frames saved in {} file(s)",
+                         iterations * options.growth_steps as u64 + 1,
+                         files)
+            }
+            Err(why) => {
+                println!("error: {}", why);
+                process::exit(1);
+            }
+        }
+    } else if let Some(ref output) = options.output {
+        let mut handle = Application::handle();
+        let result = Application::derive(&mut handle, &options.grammar, iterations)
+            .and_then(|segments| {
+                render::save_image(Path::new(output),
+                                   &segments,
+                                   options.size.0,
+                                   options.size.1,
+                                   None)
+            });
+        let result = handle.shutdown().and(result);
+        if let Err(why) = result {
+            println!("error: {}", why);
+            process::exit(1);
+        }
+    } else if let Some(port) = options.http_port {
+        // only reachable from the local machine
+        let result = RenderServer::bind(("127.0.0.1", port), Application::handle())
+            .and_then(|mut server| {
+                let address = try!(server.local_addr());
+                println!("listening on http://{}", address);
+                server.run()
+            });
+        if let Err(why) = result {
+            println!("error: {}", why);
+            process::exit(1);
+        }
+    } else if options.serve {
+        // stdout is reserved to the responses
+        let (tx, rx, worker) = Application::start_worker();
+        let stdin = io::stdin();
+        let result = protocol::serve(stdin.lock(), &mut io::stdout(), &tx, &rx);
+        if let Err(why) = view::shutdown_worker(&tx, worker).and(result) {
+            eprintln!("error: {}", why);
+            process::exit(1);
+        }
+    } else if options.repl {
+        repl::run(options.grammar);
+    } else if options.tui {
+        if let Err(why) = tui::run(&options.grammar, iterations, options.canvas_mode) {
+            println!("error: {}", why);
+            process::exit(1);
+        }
+    } else {
+        Application::run(&options.grammar, iterations);
+    }
 }
@@ -0,0 +1,214 @@
+//! Interactive shell for editing a grammar and re-deriving it on the fly.
+
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use time;
+
+use rustlsystem::grammar::Grammar;
+use rustlsystem::handle::LSystemHandle;
+use rustlsystem::process;
+use rustlsystem::render::{self, Bounds};
+use rustlsystem::rules::LRules;
+use rustlsystem::turtle::{Turtle, TurtleConfig};
+
+use app::Application;
+
+const HELP: &'static str = "Shell commands:
+    iterate [N]         iterate the L-System N times (default: 1)
+    reset               reset the L-System to its axiom
+    show                print the grammar
+    stats               print statistics about the current generation
+    processor NAME [T [C]]
+                        use the 'simple', 'chunks' or 'in-place' processor with
+                        T tasks and chunks of C symbols (automatic if omitted)
+    tune T [C]          change the tasks and chunk size of the processor
+    export FILE         interpret the current generation and save it as SVG or PNG
+    load FILE           load a grammar file
+    save FILE           save the grammar to a file
+    help                print this message
+    quit                exit the shell
+
+Any other line is a grammar definition, e.g. 'axiom A', 'rule A -> AB',
+'interpret A advance 10' or 'preset koch-curve' (see the 'grammar' module).
+The L-System is re-derived to the current generation after each definition.";
+
+/// State of the shell.
+struct Repl {
+    handle: LSystemHandle<char>,
+    grammar: Grammar,
+    /// The generation to derive.
+    generation: u64,
+    /// The generation currently derived by the worker.
+    derived: u64,
+    state_len: usize,
+    /// The duration of the last derivation, in seconds.
+    derivation_time: f64,
+    /// True if the grammar was modified since the last derivation.
+    modified: bool,
+}
+
+impl Repl {
+    /// Reload the grammar into the worker if needed, and iterate the L-System
+    /// up to the requested generation.
+    fn derive(&mut self) -> Result<(), String> {
+        let start = time::precise_time_s();
+        if self.modified {
+            let rules = Box::new(self.grammar.rules.clone());
+            try!(self.handle.load(self.grammar.axiom.clone(), rules));
+            self.derived = 0;
+            self.state_len = self.grammar.axiom.len();
+            self.modified = false;
+        }
+        while self.derived < self.generation {
+            match self.handle.iterate() {
+                Ok((n, len)) => {
+                    self.derived = n;
+                    self.state_len = len;
+                }
+                Err(why) => {
+                    self.generation = self.derived;
+                    return Err(why);
+                }
+            }
+        }
+        self.derivation_time = time::precise_time_s() - start;
+        Ok(())
+    }
+
+    fn export(&mut self, path: &Path) -> Result<(), String> {
+        let commands = try!(self.handle.interpret());
+        let segments = Turtle::new(TurtleConfig::default()).execute(&commands);
+        try!(render::save_image(path, &segments, 800, 800, None));
+        println!("{} segments saved to {}", segments.len(), path.display());
+        Ok(())
+    }
+
+    fn stats(&mut self) -> Result<(), String> {
+        let commands = try!(self.handle.interpret());
+        let segments = Turtle::new(TurtleConfig::default()).execute(&commands);
+        println!("generation       {}", self.generation);
+        println!("state length     {}", self.state_len);
+        println!("turtle commands  {}", commands.len());
+        println!("segments         {}", segments.len());
+        if let Some(bounds) = Bounds::of_segments(&segments) {
+            println!("drawing size     {:.2} x {:.2}", bounds.width(), bounds.height());
+        }
+        println!("expansion        {} max, {:.2} average",
+                 self.grammar.rules.biggest_expansion(),
+                 self.grammar.rules.average_expansion());
+        println!("derivation time  {:.3}s", self.derivation_time);
+        Ok(())
+    }
+
+    /// Execute a single line, and return false if the shell must exit.
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        let line = line.trim();
+        let (command, argument) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        match command {
+            "" => (),
+            "quit" | "exit" => return Ok(false),
+            "help" => println!("{}", HELP),
+            "iterate" => {
+                let n: u64 = match argument {
+                    "" => 1,
+                    n => try!(n.parse().map_err(|_| format!("invalid iterations number '{}'", n))),
+                };
+                self.generation += n;
+                try!(self.derive());
+                println!("generation {} : {} symbols ({:.3}s)",
+                         self.generation,
+                         self.state_len,
+                         self.derivation_time);
+            }
+            "reset" => {
+                self.generation = 0;
+                self.derived = 0;
+                try!(self.handle.reset());
+                self.state_len = self.grammar.axiom.len();
+            }
+            "processor" => {
+                let mut words = argument.split_whitespace();
+                let name = try!(words.next().ok_or(format!("missing processor name")));
+                let parameters = try!(parse_numbers(words));
+                let processor = try!(process::processor_by_name(name,
+                                                                parameters.first().cloned(),
+                                                                parameters.get(1).cloned()));
+                try!(self.handle.set_processor(processor));
+            }
+            "tune" => {
+                let parameters = try!(parse_numbers(argument.split_whitespace()));
+                match parameters.first() {
+                    Some(&tasks) => {
+                        try!(self.handle.configure_processor(Some(tasks),
+                                                             parameters.get(1).cloned()))
+                    }
+                    None => return Err(format!("missing tasks number")),
+                }
+            }
+            "show" => print!("{}", self.grammar.to_text()),
+            "stats" => try!(self.stats()),
+            "export" if !argument.is_empty() => try!(self.export(Path::new(argument))),
+            "load" if !argument.is_empty() => {
+                self.grammar = try!(Grammar::load(Path::new(argument)));
+                self.modified = true;
+                try!(self.derive());
+            }
+            "save" if !argument.is_empty() => {
+                try!(self.grammar.save(Path::new(argument)));
+            }
+            "export" | "load" | "save" => return Err(format!("missing file name")),
+            _ => {
+                try!(self.grammar.apply(line));
+                self.modified = true;
+                try!(self.derive());
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Parse the given words as numbers.
+fn parse_numbers<'a, I: Iterator<Item = &'a str>>(words: I) -> Result<Vec<usize>, String> {
+    words.map(|w| w.parse().map_err(|_| format!("invalid number '{}'", w))).collect()
+}
+
+/// Run the shell, starting with the given grammar.
+pub fn run(grammar: Grammar) {
+    let mut repl = Repl {
+        handle: Application::handle(),
+        grammar: grammar,
+        generation: 0,
+        derived: 0,
+        state_len: 0,
+        derivation_time: 0.0,
+        modified: true,
+    };
+    if let Err(why) = repl.derive() {
+        println!("error : {}", why);
+    }
+    println!("rust-lsystem shell, type 'help' for the available commands");
+
+    let stdin = io::stdin();
+    loop {
+        print!("> ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
+        match repl.execute(&line) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(why) => println!("error : {}", why),
+        }
+    }
+
+    if let Err(why) = repl.handle.shutdown() {
+        println!("error : {}", why);
+    }
+}
@@ -0,0 +1,323 @@
+//! Interactive terminal viewer, drawing the L-System with braille or ASCII
+//! characters (no GPU needed, works over SSH).
+//! The terminal is configured with 'stty', so only Unix-like systems are
+//! supported.
+
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{channel, Sender, Receiver, TryRecvError, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use time;
+
+use rustlsystem::grammar::Grammar;
+use rustlsystem::render::{Bounds, Viewport, TextCanvas, CanvasMode};
+use rustlsystem::turtle::{Segment, Turtle, TurtleConfig};
+use rustlsystem::view::{self, MessageFromViewer, Request, RequestId, Response};
+
+use app::Application;
+
+const HELP: &'static str = "[i]terate [r]eset [+/-] zoom [arrows/hjkl] pan [0] fit [q]uit";
+
+/// Key pressed by the user.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Raw-mode terminal using the alternate screen, restored when dropped.
+struct Terminal {
+    saved_mode: String,
+}
+
+impl Terminal {
+    fn enter() -> Result<Terminal, String> {
+        let saved_mode = try!(Terminal::stty(&["-g"]));
+        try!(Terminal::stty(&["-icanon", "-echo", "min", "1"]));
+        print!("\x1b[?1049h\x1b[?25l");
+        Ok(Terminal { saved_mode: saved_mode.trim().to_string() })
+    }
+
+    /// Get the terminal size, in (columns, rows).
+    fn size() -> (usize, usize) {
+        Terminal::stty(&["size"])
+            .ok()
+            .and_then(|size| {
+                let mut values = size.split_whitespace().filter_map(|v| v.parse().ok());
+                // some terminals without a window size report '0 0'
+                match (values.next(), values.next()) {
+                    (Some(rows), Some(columns)) if rows > 0 && columns > 0 => {
+                        Some((columns, rows))
+                    }
+                    _ => None,
+                }
+            })
+            .unwrap_or((80, 24))
+    }
+
+    fn stty(args: &[&str]) -> Result<String, String> {
+        let output = try!(Command::new("stty")
+            .args(args)
+            .stdin(Stdio::inherit())
+            .output()
+            .map_err(|why| format!("cannot run stty : {}", why)));
+        if !output.status.success() {
+            return Err(format!("stty failed (is the input a terminal?)"));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = Terminal::stty(&[&self.saved_mode]);
+    }
+}
+
+/// Spawn the thread reading the keys pressed by the user.
+fn spawn_key_reader() -> Receiver<Key> {
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        // unbuffered on purpose, so that every key is handled as soon as pressed
+        #[allow(clippy::unbuffered_bytes)]
+        let mut bytes = io::stdin().bytes().filter_map(|b| b.ok());
+        while let Some(byte) = bytes.next() {
+            let key = match byte {
+                0x1b => {
+                    match (bytes.next(), bytes.next()) {
+                        (Some(b'['), Some(b'A')) => Key::Up,
+                        (Some(b'['), Some(b'B')) => Key::Down,
+                        (Some(b'['), Some(b'C')) => Key::Right,
+                        (Some(b'['), Some(b'D')) => Key::Left,
+                        _ => continue,
+                    }
+                }
+                b'k' => Key::Up,
+                b'j' => Key::Down,
+                b'l' => Key::Right,
+                b'h' => Key::Left,
+                b => Key::Char(b as char),
+            };
+            if tx.send(key).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+/// State of the terminal viewer.
+struct Viewer {
+    tx: Sender<view::Request<char>>,
+    mode: CanvasMode,
+    turtle: Turtle,
+    segments: Vec<Segment>,
+    /// The current view, None to fit the whole drawing.
+    viewport: Option<Viewport>,
+    generation: u64,
+    state_len: usize,
+    axiom_len: usize,
+    /// The identifiers of the commands waiting for a response.
+    pending: Vec<RequestId>,
+    started_at: f64,
+    status: String,
+}
+
+impl Viewer {
+    fn send(&mut self, message: MessageFromViewer<char>) -> Result<(), String> {
+        if self.pending.is_empty() {
+            self.started_at = time::precise_time_s();
+        }
+        let request = Request::new(message);
+        self.pending.push(request.id);
+        self.tx.send(request).map_err(|_| format!("the worker thread has stopped"))
+    }
+
+    fn handle(&mut self, response: Response) {
+        use rustlsystem::view::MessageToViewer::*;
+        match self.pending.iter().position(|&id| id == response.id) {
+            Some(i) => {
+                self.pending.remove(i);
+            }
+            None => return,
+        }
+        let elapsed = time::precise_time_s() - self.started_at;
+        match response.message {
+            IterationFinished(n, len) => {
+                self.generation = n;
+                self.state_len = len;
+            }
+            ResettingFinised => {
+                self.generation = 0;
+                self.state_len = self.axiom_len;
+            }
+            InterpretationFinished(commands) => {
+                self.segments = self.turtle.execute(&commands);
+                self.status = format!("done in {:.3}s", elapsed);
+            }
+            Error(why) => self.status = format!("error : {}", why),
+            _ => (),
+        }
+    }
+
+    fn key(&mut self, key: Key, width: f32, height: f32) -> Result<(), String> {
+        use rustlsystem::view::MessageFromViewer::*;
+        let viewport = self.viewport(width, height);
+        let (step_x, step_y) = (width / 8.0, height / 8.0);
+        match key {
+            Key::Char('i') | Key::Char(' ') => {
+                try!(self.send(Iterate));
+                try!(self.send(Interpret));
+            }
+            Key::Char('r') => {
+                try!(self.send(ResetLSystem));
+                try!(self.send(Interpret));
+            }
+            Key::Char('0') => self.viewport = None,
+            Key::Char('+') | Key::Char('=') => {
+                let mut viewport = viewport;
+                viewport.zoom(1.5, width / 2.0, height / 2.0);
+                self.viewport = Some(viewport);
+            }
+            Key::Char('-') => {
+                let mut viewport = viewport;
+                viewport.zoom(1.0 / 1.5, width / 2.0, height / 2.0);
+                self.viewport = Some(viewport);
+            }
+            Key::Up | Key::Down | Key::Left | Key::Right => {
+                let mut viewport = viewport;
+                match key {
+                    Key::Up => viewport.pan(0.0, -step_y),
+                    Key::Down => viewport.pan(0.0, step_y),
+                    Key::Left => viewport.pan(-step_x, 0.0),
+                    _ => viewport.pan(step_x, 0.0),
+                }
+                self.viewport = Some(viewport);
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn viewport(&self, width: f32, height: f32) -> Viewport {
+        match self.viewport {
+            Some(viewport) => viewport,
+            None => {
+                let bounds = Bounds::of_segments(&self.segments)
+                    .unwrap_or(Bounds::new(0.0, 0.0));
+                Viewport::fit(&bounds, width, height, 1.0)
+            }
+        }
+    }
+
+    fn draw(&self, canvas: &mut TextCanvas) {
+        let viewport = self.viewport(canvas.width() as f32, canvas.height() as f32);
+        canvas.clear();
+        canvas.draw_segments(&self.segments, &viewport);
+
+        let activity = if !self.pending.is_empty() {
+            "working...".to_string()
+        } else {
+            self.status.clone()
+        };
+        let mut screen = String::from("\x1b[H\x1b[2J");
+        for line in canvas.lines() {
+            screen.push_str(&line);
+            screen.push_str("\r\n");
+        }
+        screen.push_str(&format!("generation {} | {} symbols | {} segments | {}\r\n{}",
+                                 self.generation,
+                                 self.state_len,
+                                 self.segments.len(),
+                                 activity,
+                                 HELP));
+        print!("{}", screen);
+        let _ = io::stdout().flush();
+    }
+}
+
+/// Run the terminal viewer on the given grammar, initially iterated the given
+/// number of times.
+pub fn run(grammar: &Grammar, iterations: u64, mode: CanvasMode) -> Result<(), String> {
+    use rustlsystem::view::MessageFromViewer::*;
+
+    let (tx, rx, worker) = Application::start_worker();
+    let terminal = try!(Terminal::enter());
+    let keys = spawn_key_reader();
+
+    let mut viewer = Viewer {
+        tx: tx,
+        mode: mode,
+        turtle: Turtle::new(TurtleConfig::default()),
+        segments: Vec::new(),
+        viewport: None,
+        generation: 0,
+        state_len: grammar.axiom.len(),
+        axiom_len: grammar.axiom.len(),
+        pending: Vec::new(),
+        started_at: 0.0,
+        status: String::new(),
+    };
+    try!(viewer.send(LoadLSystem(grammar.axiom.clone(), Box::new(grammar.rules.clone()))));
+    for _ in 0..iterations {
+        try!(viewer.send(Iterate));
+    }
+    try!(viewer.send(Interpret));
+
+    let mut size = (0, 0);
+    let mut canvas = TextCanvas::new(1, 1, viewer.mode);
+    let mut dirty = true;
+    let mut ticks = 0u64;
+    loop {
+        // worker responses
+        loop {
+            match rx.try_recv() {
+                Ok(message) => {
+                    viewer.handle(message);
+                    dirty = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    return Err(format!("the worker thread has stopped"));
+                }
+            }
+        }
+
+        // terminal resizing (checked about twice per second)
+        if ticks % 10 == 0 {
+            let new_size = Terminal::size();
+            if new_size != size {
+                size = new_size;
+                canvas = TextCanvas::new(size.0, size.1.saturating_sub(2), viewer.mode);
+                dirty = true;
+            }
+        }
+        ticks += 1;
+
+        if dirty {
+            viewer.draw(&mut canvas);
+            dirty = false;
+        }
+
+        // user input
+        match keys.recv_timeout(Duration::from_millis(50)) {
+            Ok(Key::Char('q')) => break,
+            Ok(key) => {
+                try!(viewer.key(key, canvas.width() as f32, canvas.height() as f32));
+                dirty = true;
+            }
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    drop(terminal);
+    view::shutdown_worker(&viewer.tx, worker)
+}
@@ -0,0 +1,116 @@
+//! Watch mode : the grammar file is polled for modifications, and the output
+//! image is rewritten each time it changes.
+
+use std::fs;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use rustlsystem::grammar::Grammar;
+use rustlsystem::render;
+
+use app::Application;
+
+/// Delay between two checks of the grammar file.
+const POLLING_DELAY_MS: u64 = 500;
+
+/// Watch the given grammar file until the process is interrupted, rendering
+/// it to the given image each time it is modified.
+/// If no iterations number is given, the one recommended by the grammar is used.
+/// A grammar file that goes missing is reported once, then picked up again
+/// when restored.
+pub fn run(grammar_path: &Path,
+           iterations: Option<u64>,
+           output: &Path,
+           size: (u32, u32))
+           -> Result<(), String> {
+    let mut handle = Application::handle();
+    let mut last_modified: Option<SystemTime> = None;
+    println!("watching {} (press Ctrl-C to stop)", grammar_path.display());
+
+    let mut failing = false;
+    loop {
+        match check(grammar_path, &mut last_modified) {
+            Ok(true) => {
+                failing = false;
+                let result = Grammar::load(grammar_path).and_then(|grammar| {
+                    let n = iterations.or(grammar.iterations).unwrap_or(0);
+                    Application::derive(&mut handle, &grammar, n)
+                        .and_then(|segments| {
+                            render::save_image(output, &segments, size.0, size.1, None)
+                                .map(|_| (n, segments.len()))
+                        })
+                });
+                match result {
+                    Ok((n, segments)) => {
+                        println!("{} rendered ({} iterations, {} segments)",
+                                 output.display(),
+                                 n,
+                                 segments)
+                    }
+                    Err(why) => println!("error : {}", why),
+                }
+                if !handle.is_alive() {
+                    return Err(format!("the worker thread has stopped"));
+                }
+            }
+            Ok(false) => failing = false,
+            // the file may be briefly missing while an editor saves it
+            Err(why) => {
+                if !failing {
+                    println!("error : {}", why);
+                }
+                failing = true;
+            }
+        }
+        thread::sleep(Duration::from_millis(POLLING_DELAY_MS));
+    }
+}
+
+/// Check whether the grammar file was modified since 'last_modified', updating
+/// it if so. On error 'last_modified' is left unchanged, so that the file is
+/// rendered again once it can be read.
+fn check(grammar_path: &Path, last_modified: &mut Option<SystemTime>) -> Result<bool, String> {
+    let modified = try!(fs::metadata(grammar_path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|why| format!("cannot watch {} : {}", grammar_path.display(), why)));
+    if *last_modified == Some(modified) {
+        return Ok(false);
+    }
+    *last_modified = Some(modified);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::time::Duration;
+
+    use super::check;
+
+    #[test]
+    fn missing_grammar_file() {
+        let path = env::temp_dir().join(format!("rustlsystem-watch-{}.txt", ::std::process::id()));
+        fs::write(&path, "axiom F\n").unwrap();
+        let mut last_modified = None;
+        assert_eq!(check(&path, &mut last_modified), Ok(true));
+        assert_eq!(check(&path, &mut last_modified), Ok(false));
+        let before = last_modified;
+
+        fs::remove_file(&path).unwrap();
+        assert!(check(&path, &mut last_modified).is_err());
+        assert_eq!(last_modified, before);
+
+        fs::write(&path, "axiom FF\n").unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(before.unwrap() + Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(check(&path, &mut last_modified), Ok(true));
+        assert_eq!(check(&path, &mut last_modified), Ok(false));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,454 @@
+//! Compressed representation of L-System states, as the straight-line program
+//! naturally produced by the derivation : the expansion of a symbol after 'n'
+//! iterations is the concatenation of the expansions of its successors after
+//! 'n - 1' iterations, and is shared by all its occurrences.
+//! The size of the representation grows with the alphabet size times the
+//! number of iterations, instead of the state length.
+//! These structures are standalone : neither 'LSystem' nor the processors use
+//! them, they derive their generation themselves from an axiom and a single
+//! set of rules. Table L-Systems, whose rules change along the iterations, are
+//! therefore rejected.
+
+use rules::{LRules, MAX_DECOMPOSITION_DEPTH};
+use state::LSystem;
+
+/// Node of the straight-line program.
+#[derive(Clone, Debug)]
+enum Node {
+    /// A single symbol, by index in the alphabet.
+    Symbol(usize),
+    /// The concatenation of other nodes, with the offset of each of them in
+    /// the expansion.
+    Concat(Vec<usize>, Vec<u64>),
+}
+
+/// L-System state stored as a straight-line program, supporting length
+/// queries, random access and sequential iteration without expanding it.
+pub struct CompressedState<S> {
+    /// The distinct symbols.
+    alphabet: Vec<S>,
+    nodes: Vec<Node>,
+    /// The expansion length of each node.
+    lengths: Vec<u64>,
+    root: usize,
+}
+
+impl<S: Clone + Eq> CompressedState<S> {
+    /// Derive the given axiom the given number of times with the given rules,
+    /// decompositions included, without ever expanding the state.
+    pub fn derive(axiom: &[S],
+                  rules: &LRules<S>,
+                  iterations: u64)
+                  -> Result<CompressedState<S>, String> {
+        let mut state = CompressedState {
+            alphabet: Vec::new(),
+            nodes: Vec::new(),
+            lengths: Vec::new(),
+            root: 0,
+        };
+
+        let (alphabet, successors) = try!(derivation_alphabet(axiom, rules));
+        state.alphabet = alphabet;
+
+        // expansion of each symbol, one iteration after another
+        let mut level: Vec<usize> = (0..state.alphabet.len())
+            .map(|symbol| state.push(Node::Symbol(symbol), 1))
+            .collect();
+        for _ in 0..iterations {
+            let mut next_level = Vec::with_capacity(level.len());
+            for symbol_successors in &successors {
+                let children: Vec<usize> = symbol_successors.iter().map(|&s| level[s]).collect();
+                next_level.push(try!(state.concat(children)));
+            }
+            level = next_level;
+        }
+
+        let axiom_nodes: Vec<usize> = axiom.iter()
+            .map(|s| level[symbol_index(&state.alphabet, s).unwrap()])
+            .collect();
+        state.root = try!(state.concat(axiom_nodes));
+        Ok(state)
+    }
+
+    /// Derive the state of the given L-System the given number of times.
+    /// Return an error for a table L-System (see the module documentation).
+    pub fn from_lsystem(lsystem: &LSystem<S>,
+                        iterations: u64)
+                        -> Result<CompressedState<S>, String> {
+        if lsystem.tables().is_some() {
+            return Err(format!("CompressedState : table L-Systems are not supported"));
+        }
+        CompressedState::derive(lsystem.state(), &***lsystem.rules(), iterations)
+    }
+
+    /// Get the number of symbols of the state.
+    pub fn len(&self) -> u64 {
+        self.lengths[self.root]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the number of nodes of the straight-line program, i.e. the size of
+    /// the representation.
+    pub fn nodes_number(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Get the symbol at the given position, if any.
+    pub fn get(&self, mut index: u64) -> Option<&S> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut node = self.root;
+        loop {
+            match self.nodes[node] {
+                Node::Symbol(symbol) => return Some(&self.alphabet[symbol]),
+                Node::Concat(ref children, ref offsets) => {
+                    // last child starting at or before the index
+                    let i = match offsets.binary_search(&index) {
+                        Ok(i) => {
+                            // skip the empty children starting at the same offset
+                            let mut i = i;
+                            while self.lengths[children[i]] == 0 {
+                                i += 1;
+                            }
+                            i
+                        }
+                        Err(i) => i - 1,
+                    };
+                    index -= offsets[i];
+                    node = children[i];
+                }
+            }
+        }
+    }
+
+    /// Iterate over the symbols of the state, in order.
+    pub fn iter<'a>(&'a self) -> Iter<'a, S> {
+        Iter {
+            state: self,
+            stack: vec![(self.root, 0)],
+        }
+    }
+
+    /// Expand the whole state.
+    pub fn to_vec(&self) -> Vec<S> {
+        self.iter().cloned().collect()
+    }
+
+    fn push(&mut self, node: Node, length: u64) -> usize {
+        self.nodes.push(node);
+        self.lengths.push(length);
+        self.nodes.len() - 1
+    }
+
+    fn concat(&mut self, children: Vec<usize>) -> Result<usize, String> {
+        let mut offsets = Vec::with_capacity(children.len());
+        let mut length = 0u64;
+        for &child in &children {
+            offsets.push(length);
+            length = try!(length.checked_add(self.lengths[child])
+                .ok_or(format!("CompressedState : state length overflow")));
+        }
+        Ok(self.push(Node::Concat(children, offsets), length))
+    }
+}
+
+/// Get the alphabet reachable from the given axiom, and the decomposed
+/// successors of each of its symbols (as indices in the alphabet).
+pub fn derivation_alphabet<S: Clone + Eq>(axiom: &[S],
+                                          rules: &LRules<S>)
+                                          -> Result<(Vec<S>, Vec<Vec<usize>>), String> {
+    let mut alphabet = Vec::new();
+    for symbol in axiom {
+        add_symbol(&mut alphabet, symbol);
+    }
+    let mut successors = Vec::new();
+    let mut i = 0;
+    while i < alphabet.len() {
+        let symbol = alphabet[i].clone();
+        let mut produced = Vec::new();
+        match rules.production(&symbol) {
+            Some(symbols) => {
+                for s in symbols {
+                    try!(decompose(s, rules, 0, &mut produced));
+                }
+            }
+            None => try!(decompose(&symbol, rules, 0, &mut produced)),
+        }
+        successors.push(produced.iter().map(|s| add_symbol(&mut alphabet, s)).collect());
+        i += 1;
+    }
+    Ok((alphabet, successors))
+}
+
+/// Get the index of the given symbol in the given alphabet, if any.
+pub fn symbol_index<S: Eq>(alphabet: &[S], symbol: &S) -> Option<usize> {
+    alphabet.iter().position(|s| s == symbol)
+}
+
+fn add_symbol<S: Clone + Eq>(alphabet: &mut Vec<S>, symbol: &S) -> usize {
+    match symbol_index(alphabet, symbol) {
+        Some(i) => i,
+        None => {
+            alphabet.push(symbol.clone());
+            alphabet.len() - 1
+        }
+    }
+}
+
+fn decompose<S: Clone + Eq>(symbol: &S,
+                            rules: &LRules<S>,
+                            depth: usize,
+                            result: &mut Vec<S>)
+                            -> Result<(), String> {
+    match rules.decomposition(symbol) {
+        Some(symbols) => {
+            if depth >= MAX_DECOMPOSITION_DEPTH {
+                return Err(format!("CompressedState : maximum decomposition depth ({}) exceeded",
+                                   MAX_DECOMPOSITION_DEPTH));
+            }
+            for s in symbols {
+                try!(decompose(s, rules, depth + 1, result));
+            }
+        }
+        None => result.push(symbol.clone()),
+    }
+    Ok(())
+}
+
+/// Sequential iterator over the symbols of a 'CompressedState'.
+pub struct Iter<'a, S: 'a> {
+    state: &'a CompressedState<S>,
+    /// The nodes being expanded, with the index of their next child.
+    stack: Vec<(usize, usize)>,
+}
+
+impl<'a, S> Iterator for Iter<'a, S> {
+    type Item = &'a S;
+
+    fn next(&mut self) -> Option<&'a S> {
+        while let Some((node, next)) = self.stack.pop() {
+            match self.state.nodes[node] {
+                Node::Symbol(symbol) => return Some(&self.state.alphabet[symbol]),
+                Node::Concat(ref children, _) => {
+                    if next < children.len() {
+                        self.stack.push((node, next + 1));
+                        self.stack.push((children[next], 0));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Random access to the symbols of a generation of an L-System, without
+/// deriving it : the expansion length of each symbol is computed for every
+/// iteration, so that the i-th symbol is located by descending the derivation
+/// tree, in O(generation * production length).
+pub struct DerivationIndex<S> {
+    /// The distinct symbols.
+    alphabet: Vec<S>,
+    /// The decomposed successors of each symbol.
+    successors: Vec<Vec<usize>>,
+    axiom: Vec<usize>,
+    /// The expansion length of each symbol, for each number of iterations.
+    lengths: Vec<Vec<u64>>,
+    len: u64,
+}
+
+impl<S: Clone + Eq> DerivationIndex<S> {
+    /// Index the generation obtained by deriving the given axiom the given
+    /// number of times with the given rules, decompositions included.
+    pub fn new(axiom: &[S],
+               rules: &LRules<S>,
+               generation: u64)
+               -> Result<DerivationIndex<S>, String> {
+        let (alphabet, successors) = try!(derivation_alphabet(axiom, rules));
+        let overflow = || format!("DerivationIndex : state length overflow");
+
+        let mut lengths = vec![vec![1u64; alphabet.len()]];
+        for _ in 0..generation {
+            let mut next = Vec::with_capacity(alphabet.len());
+            {
+                let previous = lengths.last().unwrap();
+                for symbol_successors in &successors {
+                    let mut length = 0u64;
+                    for &s in symbol_successors {
+                        length = try!(length.checked_add(previous[s]).ok_or_else(&overflow));
+                    }
+                    next.push(length);
+                }
+            }
+            lengths.push(next);
+        }
+
+        let axiom: Vec<usize> = axiom.iter().map(|s| symbol_index(&alphabet, s).unwrap()).collect();
+        let mut len = 0u64;
+        for &s in &axiom {
+            len = try!(len.checked_add(lengths.last().unwrap()[s]).ok_or_else(&overflow));
+        }
+        Ok(DerivationIndex {
+            alphabet: alphabet,
+            successors: successors,
+            axiom: axiom,
+            lengths: lengths,
+            len: len,
+        })
+    }
+
+    /// Index the generation obtained by deriving the state of the given
+    /// L-System the given number of times.
+    /// Return an error for a table L-System (see the module documentation).
+    pub fn from_lsystem(lsystem: &LSystem<S>,
+                        iterations: u64)
+                        -> Result<DerivationIndex<S>, String> {
+        if lsystem.tables().is_some() {
+            return Err(format!("DerivationIndex : table L-Systems are not supported"));
+        }
+        DerivationIndex::new(lsystem.state(), &***lsystem.rules(), iterations)
+    }
+
+    /// Get the number of symbols of the generation.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the symbol at the given position of the generation, if any.
+    pub fn get(&self, mut index: u64) -> Option<&S> {
+        if index >= self.len {
+            return None;
+        }
+        let generation = self.lengths.len() - 1;
+        let mut symbol = self.locate(&self.axiom, generation, &mut index);
+        for iterations in (0..generation).rev() {
+            symbol = symbol.and_then(|s| self.locate(&self.successors[s], iterations, &mut index));
+        }
+        symbol.map(|s| &self.alphabet[s])
+    }
+
+    /// Find the symbol whose expansion after the given number of iterations
+    /// contains the given position, and make the position relative to it.
+    fn locate(&self, symbols: &[usize], iterations: usize, index: &mut u64) -> Option<usize> {
+        for &symbol in symbols {
+            let length = self.lengths[iterations][symbol];
+            if *index < length {
+                return Some(symbol);
+            }
+            *index -= length;
+        }
+        None
+    }
+}
+
+/// Get the symbol at the given position of the generation obtained by deriving
+/// the given axiom the given number of times, without deriving it.
+pub fn symbol_at<S: Clone + Eq>(axiom: &[S],
+                                rules: &LRules<S>,
+                                generation: u64,
+                                index: u64)
+                                -> Result<Option<S>, String> {
+    let derivation = try!(DerivationIndex::new(axiom, rules, generation));
+    Ok(derivation.get(index).cloned())
+}
+
+#[cfg(test)]
+mod test {
+    use rules::HashMapRules;
+    use state::{LSystem, RulesTables, TableSchedule, new_rules_value};
+    use process::{LProcessor, SimpleProcessor};
+    use interpret::TurtleCommand;
+    use super::*;
+
+    #[test]
+    fn compressed_state() {
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::None);
+        rules.set_str('B', "A", TurtleCommand::None);
+        rules.set_str('C', "CE", TurtleCommand::None);
+        rules.set_decomposition_str('E', "DF");
+        rules.set_decomposition_str('D', "");
+        let mut lsystem = LSystem::new_with_char("CAB", new_rules_value(rules));
+
+        for n in 0..12 {
+            let compressed = CompressedState::from_lsystem(&lsystem, 0).ok().unwrap();
+            assert_eq!(compressed.len(), lsystem.state().len() as u64);
+            assert_eq!(&compressed.to_vec()[..], lsystem.state());
+            for (i, symbol) in lsystem.state().iter().enumerate() {
+                assert_eq!(compressed.get(i as u64), Some(symbol));
+            }
+            assert_eq!(compressed.get(compressed.len()), None);
+
+            let derived = CompressedState::from_lsystem(&lsystem, 12 - n).ok().unwrap();
+            let mut expected = lsystem.clone();
+            for _ in n..12 {
+                expected = SimpleProcessor.iterate(&expected).ok().unwrap();
+            }
+            assert_eq!(&derived.to_vec()[..], expected.state());
+            lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        }
+    }
+
+    #[test]
+    fn compressed_state_algae() {
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::None);
+        rules.set_str('B', "A", TurtleCommand::None);
+        let compressed = CompressedState::derive(&['A'], &rules, 30).ok().unwrap();
+        assert_eq!(compressed.len(), 2_178_309);
+        assert!(compressed.nodes_number() < 100);
+        assert_eq!(compressed.get(2_178_308), Some(&'A'));
+        assert_eq!(compressed.iter().filter(|&&s| s == 'A').count(), 1_346_269);
+
+        let compressed = CompressedState::derive(&['A'], &rules, 200);
+        assert!(compressed.is_err());
+    }
+
+    #[test]
+    fn derivation_index() {
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::None);
+        rules.set_str('B', "A", TurtleCommand::None);
+        rules.set_str('C', "CE", TurtleCommand::None);
+        rules.set_decomposition_str('E', "DF");
+        rules.set_decomposition_str('D', "");
+        let mut lsystem = LSystem::new_with_char("CAB", new_rules_value(rules.clone()));
+        for n in 0..10 {
+            let index = DerivationIndex::new(&['C', 'A', 'B'], &rules, n).ok().unwrap();
+            assert_eq!(index.len(), lsystem.state().len() as u64);
+            for (i, symbol) in lsystem.state().iter().enumerate() {
+                assert_eq!(index.get(i as u64), Some(symbol));
+            }
+            assert_eq!(index.get(index.len()), None);
+            lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        }
+
+        // each algae generation is a prefix of the next one
+        let algae = DerivationIndex::new(&['A'], &rules, 80).ok().unwrap();
+        assert_eq!(algae.len(), 61_305_790_721_611_591);
+        let lsystem = LSystem::new_with_char("A", new_rules_value(rules.clone()));
+        let generation = CompressedState::from_lsystem(&lsystem, 20).ok().unwrap();
+        for i in 0..generation.len() {
+            assert_eq!(algae.get(i), generation.get(i));
+        }
+        assert_eq!(symbol_at(&['A'], &rules, 80, 1).ok().unwrap(), Some('B'));
+        assert!(DerivationIndex::new(&['A'], &rules, 200).is_err());
+
+        // the rules of a table L-System change along the iterations
+        let tables = RulesTables::new(vec![new_rules_value(rules.clone()),
+                                           new_rules_value(rules)],
+                                      TableSchedule::Cyclic(vec![0, 1]))
+            .ok()
+            .unwrap();
+        let lsystem = LSystem::new_with_tables(vec!['A'], tables, None).ok().unwrap();
+        assert!(DerivationIndex::from_lsystem(&lsystem, 2).is_err());
+        assert!(CompressedState::from_lsystem(&lsystem, 2).is_err());
+    }
+}
@@ -0,0 +1,302 @@
+//! Text format describing char-based L-Systems, one definition per line:
+//!
+//! ```text
+//! # Koch snowflake
+//! axiom F--F--F
+//! rule F -> F+F--F+F
+//! interpret F advance 10
+//! interpret + rotate 60
+//! interpret - rotate -60
+//! iterations 4
+//! ```
+//!
+//! The available definitions are:
+//! - 'axiom SYMBOLS' ;
+//! - 'rule SYMBOL -> SYMBOLS', the production possibly being empty ;
+//! - 'interpret SYMBOL COMMAND', with COMMAND being one of 'advance LENGTH',
+//! 'rotate ANGLE', 'pitch ANGLE', 'roll ANGLE', 'push', 'pop' or 'none' ;
+//! - 'decompose SYMBOL -> SYMBOLS' and 'homomorphism SYMBOL -> SYMBOLS' ;
+//! - 'remove SYMBOL', removing all the rules of the symbol ;
+//! - 'preset NAME', replacing the whole definition with the named preset ;
+//! - 'iterations N', the recommended number of iterations.
+//!
+//! Empty lines and lines starting with '#' are ignored.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use interpret::TurtleCommand;
+use presets::{self, Preset, DEFAULT_STEP};
+use rules::{HashMapRules, LRules};
+use state::{LSystem, new_rules_value};
+
+/// Complete definition of a char-based L-System.
+#[derive(Clone, Debug)]
+pub struct Grammar {
+    /// The initial state.
+    pub axiom: Vec<char>,
+    /// The rules.
+    pub rules: HashMapRules<char>,
+    /// The recommended number of iterations, if any.
+    pub iterations: Option<u64>,
+}
+
+impl Grammar {
+    /// Create a new, empty grammar.
+    pub fn new() -> Grammar {
+        Grammar {
+            axiom: Vec::new(),
+            rules: HashMapRules::new(),
+            iterations: None,
+        }
+    }
+
+    /// Create the grammar of the given preset, with the given advance step.
+    pub fn from_preset(preset: &Preset, step: f32) -> Grammar {
+        Grammar {
+            axiom: preset.axiom(),
+            rules: preset.rules(step),
+            iterations: Some(preset.iterations),
+        }
+    }
+
+    /// Try and parse a grammar from its text definition.
+    pub fn parse(text: &str) -> Result<Grammar, String> {
+        let mut grammar = Grammar::new();
+        for (n, line) in text.lines().enumerate() {
+            try!(grammar.apply(line).map_err(|why| format!("line {} : {}", n + 1, why)));
+        }
+        Ok(grammar)
+    }
+
+    /// Try and load a grammar from the given text file.
+    pub fn load(path: &Path) -> Result<Grammar, String> {
+        let mut text = String::new();
+        try!(File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut text))
+            .map_err(|why| format!("cannot read {} : {}", path.display(), why)));
+        Grammar::parse(&text).map_err(|why| format!("{}, {}", path.display(), why))
+    }
+
+    /// Try and save the text definition of the grammar to the given file.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        File::create(path)
+            .and_then(|mut file| file.write_all(self.to_text().as_bytes()))
+            .map_err(|why| format!("cannot write {} : {}", path.display(), why))
+    }
+
+    /// Try and apply a single definition line to the grammar.
+    pub fn apply(&mut self, line: &str) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+        let (keyword, arguments) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+        let mut tokens = arguments.split_whitespace();
+
+        match keyword {
+            "axiom" => self.axiom = arguments.chars().filter(|c| !c.is_whitespace()).collect(),
+            "rule" => {
+                let (symbol, production) = try!(parse_rule(arguments));
+                self.rules.set_production(symbol, production);
+            }
+            "decompose" => {
+                let (symbol, decomposition) = try!(parse_rule(arguments));
+                self.rules.set_decomposition(symbol, decomposition);
+            }
+            "homomorphism" => {
+                let (symbol, homomorphism) = try!(parse_rule(arguments));
+                self.rules.set_homomorphism(symbol, homomorphism);
+            }
+            "interpret" => {
+                let symbol = try!(parse_symbol(tokens.next()));
+                let command = try!(parse_command(&mut tokens));
+                try!(expect_end(&mut tokens));
+                self.rules.set_interpretation(symbol, command);
+            }
+            // no renderer executes the turtle with the rules of the symbols
+            "tropism" | "jitter" => {
+                return Err(format!("'{}' is not supported by the grammar format", keyword))
+            }
+            "remove" => {
+                let symbol = try!(parse_symbol(tokens.next()));
+                try!(expect_end(&mut tokens));
+                self.rules.remove(&symbol);
+            }
+            "preset" => {
+                let preset = try!(presets::find(arguments)
+                    .ok_or(format!("unknown preset '{}'", arguments)));
+                *self = Grammar::from_preset(preset, DEFAULT_STEP);
+            }
+            "iterations" => {
+                self.iterations = Some(try!(parse_number(tokens.next(), "iterations number")));
+                try!(expect_end(&mut tokens));
+            }
+            _ => return Err(format!("unknown definition '{}'", keyword)),
+        }
+
+        Ok(())
+    }
+
+    /// Get the text definition of the grammar.
+    pub fn to_text(&self) -> String {
+        let mut text = format!("axiom {}\n", self.axiom.iter().cloned().collect::<String>());
+        let mut symbols = self.rules.symbols();
+        symbols.sort();
+        for symbol in symbols {
+            if let Some(production) = self.rules.production(symbol) {
+                text.push_str(&format!("rule {} -> {}\n",
+                                       symbol,
+                                       production.iter().cloned().collect::<String>()));
+            }
+            if let Some(command) = self.rules.interpretation(symbol) {
+                text.push_str(&format!("interpret {} {}\n", symbol, command_to_text(command)));
+            }
+            if let Some(decomposition) = self.rules.decomposition(symbol) {
+                text.push_str(&format!("decompose {} -> {}\n",
+                                       symbol,
+                                       decomposition.iter().cloned().collect::<String>()));
+            }
+            if let Some(homomorphism) = self.rules.homomorphism(symbol) {
+                text.push_str(&format!("homomorphism {} -> {}\n",
+                                       symbol,
+                                       homomorphism.iter().cloned().collect::<String>()));
+            }
+        }
+        if let Some(iterations) = self.iterations {
+            text.push_str(&format!("iterations {}\n", iterations));
+        }
+        text
+    }
+
+    /// Create the L-System described by the grammar, at its initial state.
+    pub fn lsystem(&self) -> LSystem<'static, char> {
+        LSystem::new(self.axiom.clone(), new_rules_value(self.rules.clone()), None)
+    }
+}
+
+fn parse_symbol(token: Option<&str>) -> Result<char, String> {
+    let token = try!(token.ok_or(format!("missing symbol")));
+    let mut chars = token.chars();
+    match (chars.next(), chars.next()) {
+        (Some(symbol), None) => Ok(symbol),
+        _ => Err(format!("invalid symbol '{}'", token)),
+    }
+}
+
+fn parse_number<T: FromStr>(token: Option<&str>, name: &str) -> Result<T, String> {
+    let token = try!(token.ok_or(format!("missing {}", name)));
+    token.parse().map_err(|_| format!("invalid {} '{}'", name, token))
+}
+
+fn expect_end<'a, I>(tokens: &mut I) -> Result<(), String>
+    where I: Iterator<Item = &'a str>
+{
+    match tokens.next() {
+        Some(token) => Err(format!("unexpected '{}'", token)),
+        None => Ok(()),
+    }
+}
+
+/// Parse a 'SYMBOL -> SYMBOLS' rule.
+fn parse_rule(arguments: &str) -> Result<(char, Vec<char>), String> {
+    let arrow = try!(arguments.find("->").ok_or(format!("missing '->' in rule")));
+    let symbol = try!(parse_symbol(Some(arguments[..arrow].trim())));
+    let symbols = arguments[arrow + 2..].chars().filter(|c| !c.is_whitespace()).collect();
+    Ok((symbol, symbols))
+}
+
+fn parse_command<'a, I>(tokens: &mut I) -> Result<TurtleCommand, String>
+    where I: Iterator<Item = &'a str>
+{
+    use interpret::TurtleCommand::*;
+    match tokens.next() {
+        Some("advance") => Ok(AdvanceBy(try!(parse_number(tokens.next(), "length")))),
+        Some("rotate") => Ok(RotateBy(try!(parse_number(tokens.next(), "angle")))),
+        Some("pitch") => Ok(PitchBy(try!(parse_number(tokens.next(), "angle")))),
+        Some("roll") => Ok(RollBy(try!(parse_number(tokens.next(), "angle")))),
+        Some("push") => Ok(PushState),
+        Some("pop") => Ok(PopState),
+        Some("none") => Ok(None),
+        Some(command) => Err(format!("unknown turtle command '{}'", command)),
+        Option::None => Err(format!("missing turtle command")),
+    }
+}
+
+/// Get the text definition of the given turtle command, e.g. 'advance 10'.
+pub fn command_to_text(command: &TurtleCommand) -> String {
+    use interpret::TurtleCommand::*;
+    match *command {
+        AdvanceBy(length) => format!("advance {}", length),
+        RotateBy(angle) => format!("rotate {}", angle),
+        PitchBy(angle) => format!("pitch {}", angle),
+        RollBy(angle) => format!("roll {}", angle),
+        PushState => format!("push"),
+        PopState => format!("pop"),
+        None => format!("none"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use interpret::TurtleCommand::*;
+    use rules::LRules;
+    use super::*;
+
+    const KOCH: &'static str = "# Koch snowflake
+axiom F--F--F
+rule F -> F+F--F+F
+
+interpret F advance 10
+interpret + rotate 60
+interpret - rotate -60
+iterations 4";
+
+    #[test]
+    fn grammar_parsing() {
+        let grammar = Grammar::parse(KOCH).ok().unwrap();
+        assert_eq!(grammar.axiom, "F--F--F".chars().collect::<Vec<char>>());
+        assert_eq!(grammar.iterations, Some(4));
+        assert_eq!(grammar.rules.production(&'F'),
+                   Some(&"F+F--F+F".chars().collect()));
+        assert_eq!(grammar.rules.interpretation(&'F'), Some(&AdvanceBy(10.0)));
+        assert_eq!(grammar.rules.production(&'+'), Option::None);
+        assert_eq!(grammar.rules.interpretation(&'-'), Some(&RotateBy(-60.0)));
+
+        let text = grammar.to_text();
+        assert_eq!(Grammar::parse(&text).ok().unwrap().to_text(), text);
+    }
+
+    #[test]
+    fn grammar_edition() {
+        let mut grammar = Grammar::new();
+        grammar.apply("preset dragon-curve").ok().unwrap();
+        assert_eq!(grammar.axiom, vec!['F', 'X']);
+        grammar.apply("rule X ->").ok().unwrap();
+        assert_eq!(grammar.rules.production(&'X'), Some(&vec![]));
+        grammar.apply("  decompose Y -> A B ").ok().unwrap();
+        assert_eq!(grammar.rules.decomposition(&'Y'), Some(&vec!['A', 'B']));
+        grammar.apply("remove F").ok().unwrap();
+        assert_eq!(grammar.rules.production(&'F'), Option::None);
+    }
+
+    #[test]
+    fn grammar_errors() {
+        assert!(Grammar::parse("axiom A\nrule A AB").err().unwrap().starts_with("line 2"));
+        assert!(Grammar::parse("rule AB -> A").is_err());
+        assert!(Grammar::parse("interpret F forward 10").is_err());
+        assert!(Grammar::parse("interpret F advance ten").is_err());
+        assert!(Grammar::parse("interpret F push now").is_err());
+        assert!(Grammar::parse("iterations").is_err());
+        assert!(Grammar::parse("preset unknown").is_err());
+        assert!(Grammar::parse("grow A").is_err());
+        assert!(Grammar::parse("tropism F 0.2").is_err());
+        assert!(Grammar::parse("jitter F 5 0.1").is_err());
+        assert!(Grammar::parse("axiom F + F").is_ok());
+    }
+}
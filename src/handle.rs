@@ -0,0 +1,477 @@
+//! Typed client of the view worker, hiding the requests and responses
+//! matching.
+//! Each command has a blocking method, waiting for its result at most for the
+//! handle timeout, and a 'start_' method returning a 'Pending' result to be
+//! polled or waited for later, so that several commands can be pipelined.
+//! A result is received only once : polling or waiting again for it fails.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::mpsc::{Sender, Receiver, RecvTimeoutError, TryRecvError};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use rules::LRules;
+use process::LProcessor;
+use interpret::{LInterpreter, TurtleCommand};
+use view::{self, CommandStream, MessageFromViewer, MessageToViewer, Request, RequestId, Response,
+           SessionId, DEFAULT_SESSION};
+
+/// Result of a command sent to the worker thread, not received yet.
+pub struct Pending<T> {
+    id: RequestId,
+    extract: fn(MessageToViewer) -> Result<T, String>,
+    result: PhantomData<T>,
+}
+
+impl<T> Pending<T> {
+    /// Get the identifier of the underlying request.
+    pub fn id(&self) -> RequestId {
+        self.id
+    }
+}
+
+fn unexpected<T>(message: MessageToViewer) -> Result<T, String> {
+    match message {
+        MessageToViewer::Error(why) => Err(why),
+        message => Err(format!("unexpected worker response : {:?}", message)),
+    }
+}
+
+fn loaded(message: MessageToViewer) -> Result<(), String> {
+    match message {
+        MessageToViewer::LoadingFinished => Ok(()),
+        message => unexpected(message),
+    }
+}
+
+fn reset(message: MessageToViewer) -> Result<(), String> {
+    match message {
+        MessageToViewer::ResettingFinised => Ok(()),
+        message => unexpected(message),
+    }
+}
+
+fn iterated(message: MessageToViewer) -> Result<(u64, usize), String> {
+    match message {
+        MessageToViewer::IterationFinished(iteration, len) => Ok((iteration, len)),
+        message => unexpected(message),
+    }
+}
+
+fn interpreted(message: MessageToViewer) -> Result<Arc<Vec<TurtleCommand>>, String> {
+    match message {
+        MessageToViewer::InterpretationFinished(commands) => Ok(commands),
+        message => unexpected(message),
+    }
+}
+
+fn streamed(message: MessageToViewer) -> Result<CommandStream, String> {
+    match message {
+        MessageToViewer::InterpretationStream(stream) => Ok(stream),
+        message => unexpected(message),
+    }
+}
+
+fn listed(message: MessageToViewer) -> Result<Vec<SessionId>, String> {
+    match message {
+        MessageToViewer::SessionList(sessions) => Ok(sessions),
+        message => unexpected(message),
+    }
+}
+
+fn dropped(message: MessageToViewer) -> Result<(), String> {
+    match message {
+        MessageToViewer::SessionDropped => Ok(()),
+        message => unexpected(message),
+    }
+}
+
+fn processor_changed(message: MessageToViewer) -> Result<(), String> {
+    match message {
+        MessageToViewer::ProcessorChanged => Ok(()),
+        message => unexpected(message),
+    }
+}
+
+fn interpreter_changed(message: MessageToViewer) -> Result<(), String> {
+    match message {
+        MessageToViewer::InterpreterChanged => Ok(()),
+        message => unexpected(message),
+    }
+}
+
+/// Handle on a view worker thread, sending its commands to a given session.
+pub struct LSystemHandle<S>
+    where S: Clone + Eq
+{
+    tx: Sender<Request<S>>,
+    rx: Receiver<Response>,
+    worker: Option<JoinHandle<()>>,
+    session: SessionId,
+    timeout: Option<Duration>,
+    /// False once the worker thread is known to have stopped.
+    alive: bool,
+    /// The requests waiting for a response.
+    pending: HashSet<RequestId>,
+    /// The responses received while waiting for another one.
+    received: HashMap<RequestId, MessageToViewer>,
+}
+
+impl<S: 'static + Clone + Eq + Send + Sync> LSystemHandle<S> {
+    /// Start a new worker thread with the given processor and interpreter, and
+    /// return its handle, without timeout and on the default session.
+    pub fn new(processor: Box<LProcessor<S> + Send>,
+               interpreter: Box<LInterpreter<S> + Send>)
+               -> LSystemHandle<S> {
+        let (tx, rx, worker) = view::spawn_worker(RefCell::new(processor),
+                                                  RefCell::new(interpreter));
+        LSystemHandle {
+            tx: tx,
+            rx: rx,
+            worker: Some(worker),
+            session: DEFAULT_SESSION.to_string(),
+            timeout: None,
+            alive: true,
+            pending: HashSet::new(),
+            received: HashMap::new(),
+        }
+    }
+}
+
+impl<S: Clone + Eq> LSystemHandle<S> {
+    /// Get the session the commands are sent to.
+    pub fn session(&self) -> &str {
+        &self.session
+    }
+
+    /// Send the next commands to the given session.
+    pub fn set_session(&mut self, session: &str) {
+        self.session = session.to_string();
+    }
+
+    /// Set the maximum waiting time of the blocking methods (None to wait
+    /// forever).
+    /// NB : a timed out command still runs, its result being discarded.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Return false if the worker thread has stopped, without blocking.
+    pub fn is_alive(&mut self) -> bool {
+        // the worker thread drops its sender when it stops
+        while self.alive {
+            match self.rx.try_recv() {
+                Ok(response) => self.store(response),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => self.alive = false,
+            }
+        }
+        self.alive
+    }
+
+    /// Load the given L-System into the session.
+    pub fn load(&mut self,
+                axiom: Vec<S>,
+                rules: Box<LRules<S> + Send + Sync>)
+                -> Result<(), String> {
+        let pending = try!(self.start_load(axiom, rules));
+        self.wait(pending)
+    }
+
+    /// Send the 'load' command without waiting for its result.
+    pub fn start_load(&mut self,
+                      axiom: Vec<S>,
+                      rules: Box<LRules<S> + Send + Sync>)
+                      -> Result<Pending<()>, String> {
+        self.send(MessageFromViewer::LoadLSystem(axiom, rules), loaded)
+    }
+
+    /// Reset the L-System of the session to its axiom.
+    pub fn reset(&mut self) -> Result<(), String> {
+        let pending = try!(self.start_reset());
+        self.wait(pending)
+    }
+
+    /// Send the 'reset' command without waiting for its result.
+    pub fn start_reset(&mut self) -> Result<Pending<()>, String> {
+        self.send(MessageFromViewer::ResetLSystem, reset)
+    }
+
+    /// Iterate the L-System of the session, and return its new iteration and
+    /// state length.
+    pub fn iterate(&mut self) -> Result<(u64, usize), String> {
+        let pending = try!(self.start_iterate());
+        self.wait(pending)
+    }
+
+    /// Send the 'iterate' command without waiting for its result.
+    pub fn start_iterate(&mut self) -> Result<Pending<(u64, usize)>, String> {
+        self.send(MessageFromViewer::Iterate, iterated)
+    }
+
+    /// Interpret the state of the L-System of the session.
+    pub fn interpret(&mut self) -> Result<Arc<Vec<TurtleCommand>>, String> {
+        let pending = try!(self.start_interpret());
+        self.wait(pending)
+    }
+
+    /// Send the 'interpret' command without waiting for its result.
+    pub fn start_interpret(&mut self) -> Result<Pending<Arc<Vec<TurtleCommand>>>, String> {
+        self.send(MessageFromViewer::Interpret, interpreted)
+    }
+
+    /// Interpret the growth of the L-System of the session toward its next
+    /// generation at the given time in [0, 1].
+    pub fn interpret_growth(&mut self, t: f32) -> Result<Arc<Vec<TurtleCommand>>, String> {
+        let pending = try!(self.start_interpret_growth(t));
+        self.wait(pending)
+    }
+
+    /// Send the 'interpret_growth' command without waiting for its result.
+    pub fn start_interpret_growth(&mut self,
+                                  t: f32)
+                                  -> Result<Pending<Arc<Vec<TurtleCommand>>>, String> {
+        self.send(MessageFromViewer::InterpretGrowth(t), interpreted)
+    }
+
+    /// Interpret the state of the L-System of the session by batches of at
+    /// most the given size, received from the returned stream while they are
+    /// produced, from a snapshot of the L-System : the worker keeps serving the
+    /// other commands meanwhile.
+    pub fn interpret_stream(&mut self, batch_size: usize) -> Result<CommandStream, String> {
+        let pending = try!(self.send(MessageFromViewer::InterpretStream(batch_size), streamed));
+        self.wait(pending)
+    }
+
+    /// Get the names of all the sessions of the worker.
+    pub fn sessions(&mut self) -> Result<Vec<SessionId>, String> {
+        let pending = try!(self.send(MessageFromViewer::ListSessions, listed));
+        self.wait(pending)
+    }
+
+    /// Drop the session and its L-System.
+    pub fn drop_session(&mut self) -> Result<(), String> {
+        let pending = try!(self.send(MessageFromViewer::DropSession, dropped));
+        self.wait(pending)
+    }
+
+    /// Replace the processor of the worker, shared by all the sessions.
+    pub fn set_processor(&mut self, processor: Box<LProcessor<S> + Send>) -> Result<(), String> {
+        let pending = try!(self.send(MessageFromViewer::SetProcessor(processor),
+                                     processor_changed));
+        self.wait(pending)
+    }
+
+    /// Change the maximum number of tasks and/or the chunk size of the
+    /// processor of the worker, None keeping the current value.
+    pub fn configure_processor(&mut self,
+                               max_tasks: Option<usize>,
+                               chunk_size: Option<usize>)
+                               -> Result<(), String> {
+        let pending = try!(self.send(MessageFromViewer::ConfigureProcessor(max_tasks, chunk_size),
+                                     processor_changed));
+        self.wait(pending)
+    }
+
+    /// Replace the interpreter of the worker, shared by all the sessions.
+    pub fn set_interpreter(&mut self,
+                           interpreter: Box<LInterpreter<S> + Send>)
+                           -> Result<(), String> {
+        let pending = try!(self.send(MessageFromViewer::SetInterpreter(interpreter),
+                                     interpreter_changed));
+        self.wait(pending)
+    }
+
+    /// Get the result of the given command if received, without blocking.
+    /// Once the result is returned, polling or waiting again for it fails.
+    pub fn poll<T>(&mut self, pending: &Pending<T>) -> Option<Result<T, String>> {
+        loop {
+            if let Some(result) = self.take(pending) {
+                return Some(result);
+            }
+            match self.rx.try_recv() {
+                Ok(response) => self.store(response),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    self.alive = false;
+                    self.pending.remove(&pending.id);
+                    return Some(Err(format!("the worker thread has stopped")));
+                }
+            }
+        }
+    }
+
+    /// Wait for the result of the given command, at most for the handle
+    /// timeout.
+    pub fn wait<T>(&mut self, pending: Pending<T>) -> Result<T, String> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(result) = self.take(&pending) {
+                return result;
+            }
+            let response = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    let remaining = if deadline > now {
+                        deadline - now
+                    } else {
+                        Duration::from_secs(0)
+                    };
+                    match self.rx.recv_timeout(remaining) {
+                        Ok(response) => Ok(response),
+                        Err(RecvTimeoutError::Timeout) => {
+                            // the late response will be discarded
+                            self.pending.remove(&pending.id);
+                            return Err(format!("timed out waiting for the worker thread"));
+                        }
+                        Err(RecvTimeoutError::Disconnected) => Err(()),
+                    }
+                }
+                None => self.rx.recv().map_err(|_| ()),
+            };
+            match response {
+                Ok(response) => self.store(response),
+                Err(()) => {
+                    self.alive = false;
+                    self.pending.remove(&pending.id);
+                    return Err(format!("the worker thread has stopped"));
+                }
+            }
+        }
+    }
+
+    /// Terminate the worker thread and wait for it.
+    pub fn shutdown(mut self) -> Result<(), String> {
+        match self.worker.take() {
+            Some(worker) => view::shutdown_worker(&self.tx, worker),
+            None => Ok(()),
+        }
+    }
+
+    fn send<T>(&mut self,
+               message: MessageFromViewer<S>,
+               extract: fn(MessageToViewer) -> Result<T, String>)
+               -> Result<Pending<T>, String> {
+        let request = Request::in_session(&self.session, message);
+        let id = request.id;
+        if self.tx.send(request).is_err() {
+            self.alive = false;
+            return Err(format!("the worker thread has stopped"));
+        }
+        self.pending.insert(id);
+        Ok(Pending {
+            id: id,
+            extract: extract,
+            result: PhantomData,
+        })
+    }
+
+    /// Get the result of the given command if received, or an error if it is
+    /// not expected anymore (already received or timed out).
+    fn take<T>(&mut self, pending: &Pending<T>) -> Option<Result<T, String>> {
+        match self.received.remove(&pending.id) {
+            Some(message) => {
+                self.pending.remove(&pending.id);
+                Some((pending.extract)(message))
+            }
+            None if !self.pending.contains(&pending.id) => {
+                Some(Err(format!("the result of request {} is not expected anymore",
+                                 pending.id)))
+            }
+            None => None,
+        }
+    }
+
+    /// Keep the given response if it is still expected.
+    fn store(&mut self, response: Response) {
+        if self.pending.contains(&response.id) {
+            self.received.insert(response.id, response.message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use rules::HashMapRules;
+    use process::{SimpleProcessor, ChunksProcessor};
+    use interpret::{SimpleInterpreter, TurtleCommand};
+    use super::*;
+
+    #[test]
+    fn lsystem_handle() {
+        let mut handle: LSystemHandle<char> = LSystemHandle::new(Box::new(SimpleProcessor),
+                                                                 Box::new(SimpleInterpreter));
+        handle.set_timeout(Some(Duration::from_secs(10)));
+        assert!(handle.iterate().is_err());
+
+        let mut rules = HashMapRules::new();
+        rules.set_str('F', "F+F", TurtleCommand::AdvanceBy(1.0));
+        rules.set_str('+', "+", TurtleCommand::RotateBy(90.0));
+        assert!(handle.load(vec!['F'], Box::new(rules.clone())).is_ok());
+        assert_eq!(handle.iterate(), Ok((1, 3)));
+        assert_eq!(handle.interpret().ok().unwrap().len(), 3);
+        assert!(handle.reset().is_ok());
+        assert_eq!(*handle.interpret_growth(0.0).ok().unwrap(),
+                   vec![TurtleCommand::AdvanceBy(1.0)]);
+        let stream = handle.interpret_stream(2).ok().unwrap();
+        assert_eq!(stream.map(|batch| batch.ok().unwrap().len()).collect::<Vec<_>>(),
+                   vec![1]);
+
+        // a pending stream does not hold the worker, and keeps its generation
+        for _ in 0..4 {
+            assert!(handle.iterate().is_ok());
+        }
+        let stream = handle.interpret_stream(1).ok().unwrap();
+        assert!(stream.next_batch().is_some());
+        handle.set_timeout(Some(Duration::from_millis(1000)));
+        assert_eq!(handle.iterate(), Ok((5, 63)));
+        assert_eq!(stream.count(), 31 - 1);
+        handle.set_timeout(Some(Duration::from_secs(10)));
+        assert!(handle.reset().is_ok());
+
+        // pipelined commands, waited for in any order
+        let first = handle.start_iterate().ok().unwrap();
+        let second = handle.start_iterate().ok().unwrap();
+        let commands = handle.start_interpret().ok().unwrap();
+        assert_eq!(handle.wait(second), Ok((2, 7)));
+        assert_eq!(handle.poll(&first), Some(Ok((1, 3))));
+        assert_eq!(handle.poll(&first),
+                   Some(Err(format!("the result of request {} is not expected anymore",
+                                    first.id()))));
+        assert!(handle.wait(first).is_err());
+        assert_eq!(handle.wait(commands).ok().unwrap().len(), 7);
+
+        handle.set_session("other");
+        assert!(handle.iterate().is_err());
+        assert!(handle.load(vec!['F'], Box::new(rules)).is_ok());
+        assert_eq!(handle.sessions(),
+                   Ok(vec!["default".to_string(), "other".to_string()]));
+        assert!(handle.drop_session().is_ok());
+
+        // reconfigured worker, keeping the L-Systems
+        assert!(handle.configure_processor(Some(2), None).is_err());
+        assert!(handle.set_processor(Box::new(ChunksProcessor::new_auto(2).ok().unwrap())).is_ok());
+        assert!(handle.configure_processor(Some(3), Some(4)).is_ok());
+        assert!(handle.set_interpreter(Box::new(SimpleInterpreter)).is_ok());
+        handle.set_session("default");
+        assert_eq!(handle.iterate(), Ok((3, 15)));
+        assert!(handle.is_alive());
+        assert!(handle.shutdown().is_ok());
+
+        // a stopped worker is detected
+        let mut handle: LSystemHandle<char> = LSystemHandle::new(Box::new(SimpleProcessor),
+                                                                 Box::new(SimpleInterpreter));
+        let pending = handle.start_iterate().ok().unwrap();
+        assert!(handle.tx.send(view::Request::new(view::MessageFromViewer::Terminate)).is_ok());
+        assert!(handle.wait(pending).is_err());
+        while handle.is_alive() {
+            ::std::thread::yield_now();
+        }
+        assert!(handle.iterate().is_err());
+    }
+}
@@ -0,0 +1,735 @@
+//! Minimal HTTP server rendering L-Systems on demand, backed by a view worker
+//! and a cache of the latest renderings, without any external dependency.
+//!
+//! 'GET /' describes the usage, and '/render' (with 'GET' or 'POST') renders a
+//! grammar, the parameters being:
+//! - 'grammar', the text definition (see the 'grammar' module), or 'preset',
+//!   the name of a preset ;
+//! - 'iterations', the generation to render (default: the grammar's
+//!   recommended one) ;
+//! - 'format', one of 'svg' (default), 'png' or 'json' (statistics) ;
+//! - 'width' and 'height', the image size (default: 800 pixels), its number of
+//!   pixels being limited (see 'RenderServer::set_max_pixels').
+//!
+//! They are read from the query string, then from the body : either the
+//! grammar as plain text, or a JSON object of parameters, e.g.
+//! '{"grammar": "axiom F\nrule F -> F+F\ninterpret F advance 10", "iterations": 4}'.
+//! The connections are handled one at a time, each being closed after its
+//! response : a request has to be received within 'REQUEST_TIMEOUT_S' seconds,
+//! its head being limited to 'MAX_HEAD_SIZE' bytes.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use grammar::Grammar;
+use handle::LSystemHandle;
+use json::Json;
+use presets::{self, DEFAULT_STEP};
+use render::{svg, png, Bounds};
+use turtle::{Segment, Turtle, TurtleConfig};
+
+/// The default number of renderings kept by the cache.
+pub const DEFAULT_CACHE_SIZE: usize = 32;
+
+/// The default maximum length of a rendered state, in symbols.
+pub const DEFAULT_MAX_LENGTH: usize = 10_000_000;
+
+/// The default maximum number of iterations of a rendering.
+pub const DEFAULT_MAX_ITERATIONS: u64 = 64;
+
+/// The default maximum number of pixels of a rendered image.
+pub const DEFAULT_MAX_PIXELS: u64 = 4096 * 4096;
+
+/// The maximum size of a request line and headers, in bytes.
+const MAX_HEAD_SIZE: usize = 8 << 10;
+
+/// The maximum size of a request body, in bytes.
+const MAX_BODY_SIZE: usize = 1 << 20;
+
+/// The maximum duration of the reception of a whole request.
+const REQUEST_TIMEOUT_S: u64 = 5;
+
+const USAGE: &'static str = "rust-lsystem render server
+
+GET|POST /render?iterations=N&format=svg|png|json&width=W&height=H
+    render the grammar given as the text body, as a JSON body of parameters
+    ('grammar', 'preset', 'iterations', 'format', 'width', 'height'), or with
+    the 'grammar' or 'preset' query parameter
+";
+
+/// Output of a rendering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RenderFormat {
+    Svg,
+    Png,
+    /// Statistics about the rendered generation.
+    Json,
+}
+
+impl RenderFormat {
+    /// Get the format of the given name ('svg', 'png' or 'json').
+    pub fn from_name(name: &str) -> Option<RenderFormat> {
+        match name {
+            "svg" => Some(RenderFormat::Svg),
+            "png" => Some(RenderFormat::Png),
+            "json" => Some(RenderFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match *self {
+            RenderFormat::Svg => "image/svg+xml",
+            RenderFormat::Png => "image/png",
+            RenderFormat::Json => "application/json",
+        }
+    }
+}
+
+/// Parsed HTTP request.
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+/// HTTP response, or error with its status and message.
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    /// True if the rendering comes from the cache, None for the other
+    /// responses.
+    cached: Option<bool>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(content_type: &'static str, body: Vec<u8>) -> Response {
+        Response {
+            status: 200,
+            content_type: content_type,
+            cached: None,
+            body: body,
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response {
+            status: status,
+            content_type: "text/plain; charset=utf-8",
+            cached: None,
+            body: format!("{}\n", message).into_bytes(),
+        }
+    }
+
+    fn write_to(&self, stream: &mut Write) -> Result<(), String> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            413 => "Payload Too Large",
+            431 => "Request Header Fields Too Large",
+            _ => "Internal Server Error",
+        };
+        let mut head = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n",
+                               self.status,
+                               reason,
+                               self.content_type,
+                               self.body.len());
+        if let Some(cached) = self.cached {
+            head.push_str(&format!("X-Cache: {}\r\n", if cached { "hit" } else { "miss" }));
+        }
+        head.push_str("Connection: close\r\n\r\n");
+        stream.write_all(head.as_bytes())
+            .and_then(|_| stream.write_all(&self.body))
+            .and_then(|_| stream.flush())
+            .map_err(|why| format!("cannot write the response : {}", why))
+    }
+}
+
+/// Decode a component of a query string ('+' and '%XX' escapes).
+fn decode_component(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let digit = |j: usize| bytes.get(j).and_then(|&b| (b as char).to_digit(16));
+        match (bytes[i], digit(i + 1), digit(i + 2)) {
+            (b'+', _, _) => decoded.push(b' '),
+            (b'%', Some(high), Some(low)) => {
+                decoded.push((high * 16 + low) as u8);
+                i += 2;
+            }
+            (byte, _, _) => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Reader of a connection, failing once the deadline of its request has
+/// passed.
+struct DeadlineReader<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl<'a> Read for DeadlineReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "request timeout"));
+        }
+        try!(self.stream.set_read_timeout(Some(self.deadline - now)));
+        let mut stream = self.stream;
+        stream.read(buf)
+    }
+}
+
+/// Get the response to a failed reception of the request.
+fn read_error(why: io::Error) -> Response {
+    match why.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+            Response::error(408, "request timeout")
+        }
+        _ => Response::error(400, &format!("{}", why)),
+    }
+}
+
+/// Read a line of the request head, of at most 'left' bytes, and decrease
+/// 'left' accordingly.
+fn read_head_line<R: BufRead>(reader: &mut R,
+                              line: &mut String,
+                              left: &mut usize)
+                              -> Result<(), Response> {
+    line.clear();
+    let read = try!(reader.by_ref().take(*left as u64).read_line(line).map_err(read_error));
+    *left -= read;
+    if *left == 0 && !line.ends_with('\n') {
+        return Err(Response::error(431, "request head too large"));
+    }
+    Ok(())
+}
+
+/// Read a request from the given stream.
+fn read_request(stream: &mut Read) -> Result<Request, Response> {
+    let bad_request = |why: String| Response::error(400, &why);
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    let mut left = MAX_HEAD_SIZE;
+    try!(read_head_line(&mut reader, &mut line, &mut left));
+    let mut words = line.split_whitespace();
+    let (method, target) = match (words.next(), words.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Err(bad_request(format!("invalid request line"))),
+    };
+
+    let mut content_length = 0;
+    let mut content_type = None;
+    loop {
+        try!(read_head_line(&mut reader, &mut line, &mut left));
+        let header = line.trim();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = match header.find(':') {
+            Some(i) => (header[..i].trim().to_lowercase(), header[i + 1..].trim()),
+            None => return Err(bad_request(format!("invalid header '{}'", header))),
+        };
+        match &name[..] {
+            "content-length" => {
+                content_length = try!(value.parse()
+                    .map_err(|_| bad_request(format!("invalid content length '{}'", value))));
+            }
+            "content-type" => content_type = Some(value.to_lowercase()),
+            _ => (),
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Err(Response::error(413, "request body too big"));
+    }
+    let mut body = vec![0; content_length];
+    try!(reader.read_exact(&mut body).map_err(read_error));
+
+    let (path, query) = match target.find('?') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => (&target[..], ""),
+    };
+    let query = query.split('&')
+        .filter(|parameter| !parameter.is_empty())
+        .map(|parameter| match parameter.find('=') {
+            Some(i) => (decode_component(&parameter[..i]), decode_component(&parameter[i + 1..])),
+            None => (decode_component(parameter), String::new()),
+        })
+        .collect();
+    Ok(Request {
+        method: method,
+        path: path.to_string(),
+        query: query,
+        content_type: content_type,
+        body: body,
+    })
+}
+
+/// Parameters of a rendering.
+struct RenderRequest {
+    grammar: Grammar,
+    iterations: u64,
+    format: RenderFormat,
+    width: u32,
+    height: u32,
+}
+
+impl RenderRequest {
+    /// Read the parameters of the given request, the body overriding the
+    /// query string, at most 'max_iterations' and images of at most
+    /// 'max_pixels' being accepted.
+    fn parse(request: &Request,
+             max_iterations: u64,
+             max_pixels: u64)
+             -> Result<RenderRequest, String> {
+        let mut parameters: HashMap<String, String> = request.query.iter().cloned().collect();
+        let body = try!(String::from_utf8(request.body.clone())
+            .map_err(|_| format!("the request body is not valid UTF-8")));
+        let is_json = match request.content_type {
+            Some(ref content_type) if content_type.contains("json") => true,
+            _ => body.trim_start().starts_with('{'),
+        };
+        if is_json {
+            match try!(Json::parse(&body)) {
+                Json::Object(members) => {
+                    for (key, value) in members {
+                        let value = match value {
+                            Json::String(s) => s,
+                            Json::Null => continue,
+                            value => value.to_string(),
+                        };
+                        parameters.insert(key, value);
+                    }
+                }
+                _ => return Err(format!("the JSON body is not an object")),
+            }
+        } else if !body.trim().is_empty() {
+            parameters.insert("grammar".to_string(), body);
+        }
+
+        let grammar = match (parameters.get("grammar"), parameters.get("preset")) {
+            (Some(text), _) => try!(Grammar::parse(text)),
+            (None, Some(name)) => {
+                let preset = try!(presets::find(name).ok_or(format!("unknown preset '{}'", name)));
+                Grammar::from_preset(preset, DEFAULT_STEP)
+            }
+            (None, None) => return Err(format!("missing grammar")),
+        };
+        let number = |key: &str, default: u64| -> Result<u64, String> {
+            match parameters.get(key) {
+                Some(value) => value.parse().map_err(|_| format!("invalid {} '{}'", key, value)),
+                None => Ok(default),
+            }
+        };
+        let iterations = try!(number("iterations", grammar.iterations.unwrap_or(0)));
+        if iterations > max_iterations {
+            return Err(format!("too many iterations ({}, {} at most)", iterations, max_iterations));
+        }
+        let format = match parameters.get("format") {
+            Some(name) => try!(RenderFormat::from_name(name)
+                .ok_or(format!("unknown format '{}'", name))),
+            None => RenderFormat::Svg,
+        };
+        let (width, height) = (try!(number("width", 800)), try!(number("height", 800)));
+        if width == 0 || height == 0 || width > 16_384 || height > 16_384 {
+            return Err(format!("invalid image size {}x{}", width, height));
+        }
+        if width * height > max_pixels {
+            return Err(format!("image too big ({}x{}, {} pixels at most)",
+                               width,
+                               height,
+                               max_pixels));
+        }
+        Ok(RenderRequest {
+            grammar: grammar,
+            iterations: iterations,
+            format: format,
+            width: width as u32,
+            height: height as u32,
+        })
+    }
+
+    /// Get the key of the rendering in the cache.
+    fn cache_key(&self) -> String {
+        format!("{}\n{} {:?} {}x{}",
+                self.grammar.to_text(),
+                self.iterations,
+                self.format,
+                self.width,
+                self.height)
+    }
+}
+
+/// The grammar loaded in the worker, with its current generation and state
+/// length.
+struct Loaded {
+    text: String,
+    generation: u64,
+    len: usize,
+}
+
+/// HTTP server rendering L-Systems with a view worker (see the module
+/// documentation).
+pub struct RenderServer {
+    listener: TcpListener,
+    handle: LSystemHandle<char>,
+    loaded: Option<Loaded>,
+    /// The renderings by key, and their keys from the oldest to the newest.
+    cache: HashMap<String, Vec<u8>>,
+    cache_order: VecDeque<String>,
+    cache_size: usize,
+    max_length: usize,
+    max_iterations: u64,
+    max_pixels: u64,
+    request_timeout: Duration,
+}
+
+impl RenderServer {
+    /// Try and listen on the given address, rendering with the worker of the
+    /// given handle.
+    pub fn bind<A: ToSocketAddrs>(address: A,
+                                  handle: LSystemHandle<char>)
+                                  -> Result<RenderServer, String> {
+        let listener = try!(TcpListener::bind(address)
+            .map_err(|why| format!("cannot start the server : {}", why)));
+        Ok(RenderServer {
+            listener: listener,
+            handle: handle,
+            loaded: None,
+            cache: HashMap::new(),
+            cache_order: VecDeque::new(),
+            cache_size: DEFAULT_CACHE_SIZE,
+            max_length: DEFAULT_MAX_LENGTH,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            max_pixels: DEFAULT_MAX_PIXELS,
+            request_timeout: Duration::from_secs(REQUEST_TIMEOUT_S),
+        })
+    }
+
+    /// Get the address the server listens on.
+    pub fn local_addr(&self) -> Result<SocketAddr, String> {
+        self.listener.local_addr().map_err(|why| format!("{}", why))
+    }
+
+    /// Set the number of renderings kept by the cache (0 to disable it).
+    pub fn set_cache_size(&mut self, cache_size: usize) {
+        self.cache_size = cache_size;
+        while self.cache_order.len() > cache_size {
+            self.evict();
+        }
+    }
+
+    /// Set the maximum length of a rendered state, the longer ones being
+    /// rejected.
+    pub fn set_max_length(&mut self, max_length: usize) {
+        self.max_length = max_length;
+    }
+
+    /// Set the maximum number of iterations of a rendering, the requests of
+    /// more being rejected.
+    pub fn set_max_iterations(&mut self, max_iterations: u64) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Set the maximum number of pixels of a rendered image, the requests of
+    /// bigger ones being rejected.
+    pub fn set_max_pixels(&mut self, max_pixels: u64) {
+        self.max_pixels = max_pixels;
+    }
+
+    /// Handle the connections forever, or until the listener fails.
+    pub fn run(&mut self) -> Result<(), String> {
+        loop {
+            try!(self.serve_one());
+        }
+    }
+
+    /// Wait for a single connection and answer its request.
+    /// NB : only the listener failures are reported, the connection ones
+    /// concerning the client alone.
+    pub fn serve_one(&mut self) -> Result<(), String> {
+        let (mut stream, _) = try!(self.listener
+            .accept()
+            .map_err(|why| format!("cannot accept a connection : {}", why)));
+        let _ = self.handle_connection(&mut stream);
+        Ok(())
+    }
+
+    /// Terminate the worker thread of the server.
+    pub fn shutdown(self) -> Result<(), String> {
+        self.handle.shutdown()
+    }
+
+    fn handle_connection(&mut self, stream: &mut TcpStream) -> Result<(), String> {
+        let mut reader = DeadlineReader {
+            stream: stream,
+            deadline: Instant::now() + self.request_timeout,
+        };
+        let response = match read_request(&mut reader) {
+            Ok(request) => self.respond(&request),
+            Err(response) => response,
+        };
+        try!(stream.set_write_timeout(Some(self.request_timeout))
+            .map_err(|why| format!("{}", why)));
+        response.write_to(stream)
+    }
+
+    fn respond(&mut self, request: &Request) -> Response {
+        match (&request.method[..], &request.path[..]) {
+            ("GET", "/") => Response::new("text/plain; charset=utf-8", USAGE.as_bytes().to_vec()),
+            ("GET", "/render") | ("POST", "/render") => {
+                match RenderRequest::parse(request, self.max_iterations, self.max_pixels) {
+                    Ok(render) => self.render(&render),
+                    Err(why) => Response::error(400, &why),
+                }
+            }
+            (_, "/") | (_, "/render") => Response::error(405, "method not allowed"),
+            (_, path) => Response::error(404, &format!("no resource at '{}'", path)),
+        }
+    }
+
+    fn render(&mut self, render: &RenderRequest) -> Response {
+        let key = render.cache_key();
+        if let Some(body) = self.cache.get(&key) {
+            let mut response = Response::new(render.format.content_type(), body.clone());
+            response.cached = Some(true);
+            return response;
+        }
+        let (generation, len, segments) = match self.derive(&render.grammar, render.iterations) {
+            Ok(derived) => derived,
+            Err(response) => return response,
+        };
+        let body = match render.format {
+            RenderFormat::Svg => {
+                let options = svg::SvgOptions {
+                    width: render.width,
+                    height: render.height,
+                    ..svg::SvgOptions::default()
+                };
+                svg::to_svg(&segments, &options).into_bytes()
+            }
+            RenderFormat::Png => {
+                let options = png::PngOptions {
+                    width: render.width,
+                    height: render.height,
+                    ..png::PngOptions::default()
+                };
+                png::to_png(&segments, &options)
+            }
+            RenderFormat::Json => {
+                let bounds = Bounds::of_segments(&segments).unwrap_or(Bounds::new(0.0, 0.0));
+                let stats = Json::object(vec![("generation", Json::Number(generation as f64)),
+                                              ("length", Json::Number(len as f64)),
+                                              ("segments", Json::Number(segments.len() as f64)),
+                                              ("width", Json::Number(bounds.width() as f64)),
+                                              ("height", Json::Number(bounds.height() as f64))]);
+                format!("{}\n", stats).into_bytes()
+            }
+        };
+        self.store(key, body.clone());
+        let mut response = Response::new(render.format.content_type(), body);
+        response.cached = Some(false);
+        response
+    }
+
+    /// Derive the given grammar up to the given generation, continuing from the
+    /// one loaded in the worker if possible, and return the generation, its
+    /// length and its segments.
+    fn derive(&mut self,
+              grammar: &Grammar,
+              iterations: u64)
+              -> Result<(u64, usize, Vec<Segment>), Response> {
+        let failure = |why: String| Response::error(500, &why);
+        let text = grammar.to_text();
+        let mut loaded = match self.loaded.take() {
+            Some(loaded) => loaded,
+            None => {
+                Loaded {
+                    text: String::new(),
+                    generation: 0,
+                    len: 0,
+                }
+            }
+        };
+        if loaded.text != text {
+            try!(self.handle
+                .load(grammar.axiom.clone(), Box::new(grammar.rules.clone()))
+                .map_err(&failure));
+            loaded = Loaded {
+                text: text,
+                generation: 0,
+                len: grammar.axiom.len(),
+            };
+        } else if loaded.generation > iterations {
+            try!(self.handle.reset().map_err(&failure));
+            loaded.generation = 0;
+            loaded.len = grammar.axiom.len();
+        }
+        while loaded.generation < iterations {
+            if loaded.len > self.max_length {
+                break;
+            }
+            // on failure, the loaded grammar is forgotten
+            let (generation, len) = try!(self.handle.iterate().map_err(&failure));
+            loaded.generation = generation;
+            loaded.len = len;
+        }
+        let (generation, len) = (loaded.generation, loaded.len);
+        self.loaded = Some(loaded);
+        if len > self.max_length || generation < iterations {
+            return Err(Response::error(413,
+                                       &format!("state too long ({} symbols at generation {}, \
+                                                 {} at most)",
+                                                len,
+                                                generation,
+                                                self.max_length)));
+        }
+        let commands = try!(self.handle.interpret().map_err(&failure));
+        Ok((generation, len, Turtle::new(TurtleConfig::default()).execute(&commands)))
+    }
+
+    /// Keep the given rendering, forgetting the oldest one if needed.
+    fn store(&mut self, key: String, body: Vec<u8>) {
+        if self.cache_size == 0 {
+            return;
+        }
+        while self.cache_order.len() >= self.cache_size {
+            self.evict();
+        }
+        self.cache_order.push_back(key.clone());
+        self.cache.insert(key, body);
+    }
+
+    fn evict(&mut self) {
+        if let Some(key) = self.cache_order.pop_front() {
+            self.cache.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Duration;
+
+    use handle::LSystemHandle;
+    use json::Json;
+    use process::SimpleProcessor;
+    use interpret::SimpleInterpreter;
+    use super::{RenderServer, MAX_HEAD_SIZE, decode_component, read_request};
+
+    /// Send the given raw request to the server, let it answer, and return the
+    /// response status, head and body.
+    fn request(server: &mut RenderServer, request: &str) -> (u16, String, Vec<u8>) {
+        let mut stream = TcpStream::connect(server.local_addr().ok().unwrap()).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        assert!(server.serve_one().is_ok());
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(response[..end].to_vec()).unwrap();
+        let status = head[9..12].parse().unwrap();
+        (status, head, response[end + 4..].to_vec())
+    }
+
+    fn post(server: &mut RenderServer, target: &str, body: &str) -> (u16, String, Vec<u8>) {
+        request(server,
+                &format!("POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+                         target,
+                         body.len(),
+                         body))
+    }
+
+    #[test]
+    fn render_server() {
+        assert_eq!(decode_component("a+b%2Fc%3d%zz%"), "a b/c=%zz%");
+
+        let handle = LSystemHandle::new(Box::new(SimpleProcessor), Box::new(SimpleInterpreter));
+        let mut server = RenderServer::bind("127.0.0.1:0", handle).ok().unwrap();
+        server.set_max_length(1_000);
+        let grammar = "axiom F\nrule F -> F+F-F\ninterpret F advance 10\ninterpret + rotate 90\n\
+                       interpret - rotate -90\n";
+
+        let (status, _, body) = request(&mut server, "GET / HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 200);
+        assert!(String::from_utf8(body).unwrap().contains("/render"));
+
+        let (status, head, body) = post(&mut server, "/render?iterations=2&format=json", grammar);
+        assert_eq!(status, 200);
+        assert!(head.contains("Content-Type: application/json"));
+        assert!(head.contains("X-Cache: miss"));
+        let stats = Json::parse(String::from_utf8(body).unwrap().trim()).ok().unwrap();
+        assert_eq!(stats.get("generation").and_then(Json::as_usize), Some(2));
+        assert_eq!(stats.get("length").and_then(Json::as_usize), Some(17));
+        assert_eq!(stats.get("segments").and_then(Json::as_usize), Some(9));
+        let (_, head, _) = post(&mut server, "/render?iterations=2&format=json", grammar);
+        assert!(head.contains("X-Cache: hit"));
+
+        // JSON parameters, continuing from the loaded generation
+        let json = format!(r#"{{"grammar": {}, "iterations": 3, "width": 120}}"#,
+                           Json::String(grammar.to_string()));
+        let (status, head, body) = post(&mut server, "/render", &json);
+        assert_eq!(status, 200);
+        assert!(head.contains("Content-Type: image/svg+xml"));
+        assert!(String::from_utf8(body).unwrap().contains("width=\"120\""));
+
+        let (status, head, body) =
+            request(&mut server,
+                    "GET /render?preset=koch-curve&iterations=1&format=png HTTP/1.1\r\n\r\n");
+        assert_eq!(status, 200);
+        assert!(head.contains("Content-Type: image/png"));
+        assert_eq!(&body[..4], b"\x89PNG");
+
+        assert_eq!(post(&mut server, "/render", "axiom F\nrule F F").0, 400);
+        assert_eq!(post(&mut server, "/render?format=gif", grammar).0, 400);
+        let (status, _, body) = post(&mut server, "/render?width=16384&height=16384", grammar);
+        assert_eq!(status, 400);
+        assert!(String::from_utf8(body).unwrap().contains("image too big"));
+        server.set_max_pixels(800 * 800);
+        assert_eq!(post(&mut server, "/render?width=400&height=1600", grammar).0, 200);
+        assert_eq!(post(&mut server, "/render?width=800&height=801", grammar).0, 400);
+        assert_eq!(post(&mut server, "/render?iterations=10", grammar).0, 413);
+        // non-growing states are bounded by the number of iterations
+        server.set_max_iterations(100);
+        let constant = "axiom F\ninterpret F advance 1\n";
+        assert_eq!(post(&mut server, "/render?iterations=100&format=json", constant).0, 200);
+        let (status, _, body) = post(&mut server,
+                                     "/render?iterations=18446744073709551615",
+                                     constant);
+        assert_eq!(status, 400);
+        assert!(String::from_utf8(body).unwrap().contains("too many iterations"));
+        assert_eq!(post(&mut server, "/missing", "").0, 404);
+        assert_eq!(request(&mut server, "DELETE /render HTTP/1.1\r\n\r\n").0, 405);
+        assert_eq!(post(&mut server, "/render?iterations=1&format=json", grammar).0, 200);
+
+        // the whole request has to be received before the deadline
+        server.request_timeout = Duration::from_millis(200);
+        let (status, _, _) = request(&mut server, "GET / HTTP/1.1\r\nHost: localh");
+        assert_eq!(status, 408);
+        assert!(server.shutdown().is_ok());
+
+        // the request head is limited
+        let status = |request: &str| match read_request(&mut request.as_bytes()) {
+            Ok(_) => 200,
+            Err(response) => response.status,
+        };
+        let header = format!("X-Padding: {}\r\n", "a".repeat(MAX_HEAD_SIZE / 2));
+        assert_eq!(status(&format!("GET / HTTP/1.1\r\n{}\r\n", header)), 200);
+        assert_eq!(status(&format!("GET / HTTP/1.1\r\n{}{}\r\n", header, header)), 431);
+        assert_eq!(status(&format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEAD_SIZE))),
+                   431);
+    }
+}
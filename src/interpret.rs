@@ -1,15 +1,30 @@
+use std::mem;
+
+use super::rules::LRules;
 use super::state::LSystem;
 
+/// Maximum recursion depth when applying the homomorphism rules: expanding a
+/// symbol beyond this depth is an error, like for the decomposition rules.
+pub const MAX_HOMOMORPHISM_DEPTH: usize = 16;
+
 /// Enumerates all the commands needed for a Turtle-type rendering of an
 /// L-System's state.
-/// Only 2D is supported for now.
+/// 2D L-Systems only need 'RotateBy'; 'PitchBy' and 'RollBy' allow for 3D
+/// ones.
 #[derive(Clone, Debug, PartialEq)]
 pub enum TurtleCommand {
     /// Advance the turtle by a certain amount (forward if this amount is
     /// positive, backward otherwise), in pixels by default.
     AdvanceBy(f32),
-    /// Rotate the turtle by a certain angle, in degrees by default.
+    /// Rotate the turtle by a certain angle around its up axis (turn left
+    /// if positive), in degrees by default.
     RotateBy(f32),
+    /// Rotate the turtle by a certain angle around its left axis (pitch
+    /// down if positive), in degrees by default.
+    PitchBy(f32),
+    /// Rotate the turtle by a certain angle around its heading axis (roll
+    /// left if positive), in degrees by default.
+    RollBy(f32),
     /// Push (save) the current turtle state (position and angle) to the stack.
     PushState,
     /// Pop (restore) the last turtle state from the stack.
@@ -20,11 +35,187 @@ pub enum TurtleCommand {
 
 unsafe impl Send for TurtleCommand {}
 
+impl TurtleCommand {
+    /// Get the command with its length or angle multiplied by the given factor.
+    pub fn scaled(&self, factor: f32) -> TurtleCommand {
+        use self::TurtleCommand::*;
+        match *self {
+            AdvanceBy(length) => AdvanceBy(length * factor),
+            RotateBy(angle) => RotateBy(angle * factor),
+            PitchBy(angle) => PitchBy(angle * factor),
+            RollBy(angle) => RollBy(angle * factor),
+            ref command => command.clone(),
+        }
+    }
+}
+
+/// Expand the given symbols through the homomorphism rules and call the given
+/// function, in order, with every resulting symbol having an interpretation,
+/// stopping as soon as it returns false.
+/// Return false if the interpretation was stopped, or an error if the maximum
+/// homomorphism depth is exceeded.
+pub fn for_each_interpretation<S, F>(symbols: &[S],
+                                     rules: &LRules<S>,
+                                     f: &mut F)
+                                     -> Result<bool, String>
+    where S: Eq,
+          F: FnMut(&S, &TurtleCommand) -> bool
+{
+    for s in symbols {
+        if !try!(interpret_symbol(s, rules, 0, f)) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn interpret_symbol<S, F>(symbol: &S,
+                          rules: &LRules<S>,
+                          depth: usize,
+                          f: &mut F)
+                          -> Result<bool, String>
+    where S: Eq,
+          F: FnMut(&S, &TurtleCommand) -> bool
+{
+    match rules.homomorphism(symbol) {
+        Some(symbols) => {
+            if depth >= MAX_HOMOMORPHISM_DEPTH {
+                return Err(format!("for_each_interpretation : maximum homomorphism depth ({}) \
+                                    exceeded",
+                                   MAX_HOMOMORPHISM_DEPTH));
+            }
+            for s in symbols {
+                if !try!(interpret_symbol(s, rules, depth + 1, f)) {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        None => {
+            Ok(match rules.interpretation(symbol) {
+                Some(command) => f(symbol, command),
+                None => true,
+            })
+        }
+    }
+}
+
+/// Interpret the growth of the given symbols toward their next generation at
+/// the given time 't' in [0, 1], calling the given function with every
+/// resulting (symbol, command) pair : 't' = 0 gives the drawing of the current
+/// generation and 't' = 1 the one of the next generation.
+/// Each symbol grows into its production : the symbol itself is drawn with
+/// its lengths and angles scaled by '1 - t', then its successors with their
+/// lengths scaled by 't' but the angles of the production, followed by the
+/// reverse of their turns (outside of any branch) scaled by 't - 1', so that
+/// the turtle keeps the heading of the symbol at 't' = 0.
+/// Decompositions are not applied.
+/// Return an error if the maximum homomorphism depth is exceeded.
+pub fn for_each_growth_interpretation<S, F>(symbols: &[S],
+                                            rules: &LRules<S>,
+                                            t: f32,
+                                            f: &mut F)
+                                            -> Result<(), String>
+    where S: Eq,
+          F: FnMut(&S, &TurtleCommand)
+{
+    use self::TurtleCommand::*;
+
+    let t = t.max(0.0).min(1.0);
+    for s in symbols {
+        let production = match rules.production(s) {
+            Some(production) if t > 0.0 && !(production.len() == 1 && production[0] == *s) => {
+                production
+            }
+            _ => {
+                try!(interpret_symbol(s, rules, 0, &mut |symbol, command| {
+                    f(symbol, command);
+                    true
+                }));
+                continue;
+            }
+        };
+        if t < 1.0 {
+            try!(interpret_symbol(s, rules, 0, &mut |symbol, command| {
+                f(symbol, &command.scaled(1.0 - t));
+                true
+            }));
+        }
+        let mut turns = Vec::new();
+        let mut depth = 0usize;
+        for successor in production {
+            try!(interpret_symbol(successor, rules, 0, &mut |symbol, command| {
+                match *command {
+                    PushState => depth += 1,
+                    PopState => depth = depth.saturating_sub(1),
+                    RotateBy(_) | PitchBy(_) | RollBy(_) if depth == 0 && t < 1.0 => {
+                        turns.push((successor, command.clone()))
+                    }
+                    _ => (),
+                }
+                match *command {
+                    AdvanceBy(length) => f(symbol, &AdvanceBy(length * t)),
+                    _ => f(symbol, command),
+                }
+                true
+            }));
+        }
+        for &(successor, ref command) in turns.iter().rev() {
+            f(successor, &command.scaled(t - 1.0));
+        }
+    }
+    Ok(())
+}
+
+/// Get the turtle commands of the growth of the given L-System toward its next
+/// generation at the given time 't' (see 'for_each_growth_interpretation').
+pub fn interpret_growth<S: Eq>(lsystem: &LSystem<S>,
+                               t: f32)
+                               -> Result<Vec<TurtleCommand>, String> {
+    // without a valid next table, the next iteration fails anyway
+    let rules = lsystem.next_rules().unwrap_or(lsystem.rules());
+    let mut commands = Vec::with_capacity(lsystem.state().len());
+    try!(for_each_growth_interpretation(lsystem.state(), &***rules, t, &mut |_, command| {
+        match *command {
+            TurtleCommand::None => (),
+            _ => commands.push(command.clone()),
+        }
+    }));
+    Ok(commands)
+}
+
 /// L-System interpreters translate the state of an L-System into a sequence
 /// of drawing instructions in order to represent it (think Turtle graphics
 /// from Logo).
 pub trait LInterpreter<S: Clone + Eq> {
     fn interpret(&mut self, lsystem: &LSystem<S>) -> Result<Vec<TurtleCommand>, String>;
+
+    /// Interpret the given L-System state by batches of at most the given
+    /// size, calling the given function with each of them in order, and
+    /// stopping early if it returns false.
+    /// The default implementation splits the result of 'interpret' ; streaming
+    /// interpreters should override it so that the whole result is never held
+    /// in memory.
+    fn interpret_batches(&mut self,
+                         lsystem: &LSystem<S>,
+                         batch_size: usize,
+                         f: &mut FnMut(Vec<TurtleCommand>) -> bool)
+                         -> Result<(), String> {
+        let mut commands = try!(self.interpret(lsystem)).into_iter();
+        loop {
+            let batch: Vec<TurtleCommand> = commands.by_ref().take(batch_size.max(1)).collect();
+            if batch.is_empty() || !f(batch) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Get a new interpreter producing the same commands, so that an
+    /// interpretation can run on another thread, or None (the default) if the
+    /// interpreter cannot be duplicated.
+    fn duplicate(&self) -> Option<Box<LInterpreter<S> + Send>> {
+        None
+    }
 }
 
 /// Simple, linear L-System interpreter.
@@ -38,20 +229,47 @@ impl<S> LInterpreter<S> for SimpleInterpreter
         let rules = lsystem.rules();
         let mut commands = Vec::with_capacity(lsystem.state().len());
 
-        for s in lsystem.state() {
-            match rules.interpretation(&s) {
-                Some(command) => {
-                    match *command {
-                        TurtleCommand::None => (), // save memory
-                        _ => commands.push(command.clone()),
+        try!(for_each_interpretation(lsystem.state(), &***rules, &mut |_, command| {
+            match *command {
+                TurtleCommand::None => (), // save memory
+                _ => commands.push(command.clone()),
+            }
+            true
+        }));
+        commands.shrink_to_fit();
+
+        Ok(commands)
+    }
+
+    fn interpret_batches(&mut self,
+                         lsystem: &LSystem<S>,
+                         batch_size: usize,
+                         f: &mut FnMut(Vec<TurtleCommand>) -> bool)
+                         -> Result<(), String> {
+        let batch_size = batch_size.max(1);
+        let rules = lsystem.rules();
+        let mut batch = Vec::with_capacity(batch_size);
+
+        let finished = try!(for_each_interpretation(lsystem.state(), &***rules, &mut |_, command| {
+            match *command {
+                TurtleCommand::None => true,
+                _ => {
+                    batch.push(command.clone());
+                    if batch.len() < batch_size {
+                        return true;
                     }
+                    f(mem::replace(&mut batch, Vec::with_capacity(batch_size)))
                 }
-                None => (),
             }
+        }));
+        if finished && !batch.is_empty() {
+            f(batch);
         }
-        commands.shrink_to_fit();
+        Ok(())
+    }
 
-        Ok(commands)
+    fn duplicate(&self) -> Option<Box<LInterpreter<S> + Send>> {
+        Some(Box::new(SimpleInterpreter))
     }
 }
 
@@ -60,7 +278,8 @@ mod test {
     use rules::HashMapRules;
     use state::{LSystem, new_rules_value};
     use process::{LProcessor, SimpleProcessor};
-    use super::{LInterpreter, SimpleInterpreter};
+    use super::{LInterpreter, SimpleInterpreter, TurtleCommand, MAX_HOMOMORPHISM_DEPTH,
+                for_each_interpretation, interpret_growth};
     use super::TurtleCommand::*;
 
     #[test]
@@ -91,4 +310,151 @@ mod test {
             assert_eq!(commands[i], expected_commands[i]);
         }
     }
+
+    #[test]
+    fn simple_interpreter_homomorphism() {
+        let mut rules: HashMapRules<char> = HashMapRules::new();
+        rules.set_str('A', "AB", None);
+        rules.set_str('F', "F", AdvanceBy(1f32));
+        rules.set_str('+', "+", RotateBy(90f32));
+        rules.set_homomorphism_str('A', "F+");
+        rules.set_homomorphism_str('B', "FB");
+        let mut lsystem = LSystem::new_with_char("A", new_rules_value(rules));
+        lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        assert_eq!(lsystem.state(), &['A', 'B']);
+
+        // 'B' expands into itself forever
+        assert!(SimpleInterpreter.interpret(&lsystem).is_err());
+        assert!(SimpleInterpreter.interpret_batches(&lsystem, 8, &mut |_| true).is_err());
+        assert!(interpret_growth(&lsystem, 0.5).is_err());
+
+        // 'C' expands into 'F' at the maximum depth, through 'a', 'b'...
+        let mut rules: HashMapRules<char> = HashMapRules::new();
+        rules.set_str('F', "F", AdvanceBy(1f32));
+        rules.set_str('+', "+", RotateBy(90f32));
+        rules.set_homomorphism_str('A', "F+");
+        let mut last = 'C';
+        for depth in 0..MAX_HOMOMORPHISM_DEPTH - 1 {
+            let symbol = (b'a' + depth as u8) as char;
+            rules.set_homomorphism(last, vec![symbol]);
+            last = symbol;
+        }
+        rules.set_homomorphism(last, vec!['F']);
+        let lsystem = LSystem::new_with_char("AC", new_rules_value(rules.clone()));
+        let commands = SimpleInterpreter.interpret(&lsystem).ok().unwrap();
+        assert_eq!(commands, vec![AdvanceBy(1.0), RotateBy(90.0), AdvanceBy(1.0)]);
+        rules.set_homomorphism(last, vec!['A']);
+        let lsystem = LSystem::new_with_char("AC", new_rules_value(rules));
+        assert!(SimpleInterpreter.interpret(&lsystem).is_err());
+    }
+
+    #[test]
+    fn growth_interpreter() {
+        let mut rules: HashMapRules<char> = HashMapRules::new();
+        rules.set_str('F', "F[+F]F", AdvanceBy(4f32));
+        rules.set_str('+', "+", RotateBy(90f32));
+        rules.set_str('[', "[", PushState);
+        rules.set_str(']', "]", PopState);
+        let mut lsystem = LSystem::new_with_char("F", new_rules_value(rules));
+
+        let current = SimpleInterpreter.interpret(&lsystem).ok().unwrap();
+        assert_eq!(interpret_growth(&lsystem, 0.0).ok().unwrap(), current);
+        assert_eq!(interpret_growth(&lsystem, 0.5).ok().unwrap(),
+                   vec![AdvanceBy(2.0), AdvanceBy(2.0), PushState, RotateBy(90.0), AdvanceBy(2.0),
+                        PopState, AdvanceBy(2.0)]);
+        assert_eq!(interpret_growth(&lsystem, -1.0).ok().unwrap(), current);
+
+        let grown = interpret_growth(&lsystem, 2.0).ok().unwrap();
+        lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        assert_eq!(grown, SimpleInterpreter.interpret(&lsystem).ok().unwrap());
+    }
+
+    #[test]
+    fn growth_interpreter_replaced_symbols() {
+        // Sierpinski arrowhead : no production contains its own symbol
+        let mut rules: HashMapRules<char> = HashMapRules::new();
+        rules.set_str('A', "B-A-B", AdvanceBy(1f32));
+        rules.set_str('B', "A+B+A", AdvanceBy(1f32));
+        rules.set_str('+', "+", RotateBy(60f32));
+        rules.set_str('-', "-", RotateBy(-60f32));
+        let mut lsystem = LSystem::new_with_char("A", new_rules_value(rules));
+        lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+
+        let current = SimpleInterpreter.interpret(&lsystem).ok().unwrap();
+        assert_eq!(interpret_growth(&lsystem, 0.0).ok().unwrap(), current);
+        let grown = interpret_growth(&lsystem, 1.0).ok().unwrap();
+        let next = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        assert_eq!(grown, SimpleInterpreter.interpret(&next).ok().unwrap());
+
+        // the first 'B' shrinks while its successors grow with their angles,
+        // which are then partly undone
+        let growth = interpret_growth(&lsystem, 0.5).ok().unwrap();
+        assert_eq!(&growth[..8],
+                   &[AdvanceBy(0.5), AdvanceBy(0.5), RotateBy(60.0), AdvanceBy(0.5),
+                     RotateBy(60.0), AdvanceBy(0.5), RotateBy(-30.0), RotateBy(-30.0)]);
+        assert_eq!(growth[8], RotateBy(-60.0));
+        let mut advances = 0;
+        for command in &growth {
+            if let AdvanceBy(_) = *command {
+                advances += 1;
+            }
+        }
+        assert_eq!(advances, 3 * (1 + 3));
+    }
+
+    struct BufferedInterpreter;
+
+    impl LInterpreter<char> for BufferedInterpreter {
+        fn interpret(&mut self, lsystem: &LSystem<char>) -> Result<Vec<TurtleCommand>, String> {
+            SimpleInterpreter.interpret(lsystem)
+        }
+    }
+
+    #[test]
+    fn batched_interpretation() {
+        let mut rules: HashMapRules<char> = HashMapRules::new();
+        rules.set_str('F', "F+F", AdvanceBy(1f32));
+        rules.set_str('+', "+", RotateBy(90f32));
+        rules.set_str('X', "X", None);
+        let mut lsystem = LSystem::new_with_char("FX", new_rules_value(rules));
+        for _ in 0..4 {
+            lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        }
+        let commands = SimpleInterpreter.interpret(&lsystem).ok().unwrap();
+        assert_eq!(commands.len(), 31);
+
+        let interpreters: Vec<Box<LInterpreter<char>>> = vec![Box::new(SimpleInterpreter),
+                                                              Box::new(BufferedInterpreter)];
+        for mut interpreter in interpreters {
+            let mut batches = Vec::new();
+            interpreter.interpret_batches(&lsystem, 8, &mut |batch| {
+                    batches.push(batch);
+                    true
+                })
+                .ok()
+                .unwrap();
+            assert_eq!(batches.iter().map(|b| b.len()).collect::<Vec<_>>(), vec![8, 8, 8, 7]);
+            assert_eq!(batches.concat(), commands);
+
+            // stopped after the second batch
+            let mut received = 0;
+            interpreter.interpret_batches(&lsystem, 8, &mut |_| {
+                    received += 1;
+                    received < 2
+                })
+                .ok()
+                .unwrap();
+            assert_eq!(received, 2);
+        }
+
+        // the traversal itself stops early
+        let mut visited = 0;
+        assert!(!for_each_interpretation(lsystem.state(), &***lsystem.rules(), &mut |_, _| {
+                visited += 1;
+                visited < 3
+            })
+            .ok()
+            .unwrap());
+        assert_eq!(visited, 3);
+    }
 }
@@ -0,0 +1,317 @@
+//! Minimal JSON values, without any external dependency : enough to exchange
+//! single-line messages with other processes (see the 'protocol' module).
+
+use std::fmt;
+use std::str::Chars;
+use std::iter::Peekable;
+
+/// The maximum nesting depth of the arrays and objects of a parsed value.
+pub const MAX_JSON_DEPTH: usize = 128;
+
+/// JSON value, the members of the objects being kept in order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Try and parse a JSON value, the whole text having to be consumed.
+    pub fn parse(text: &str) -> Result<Json, String> {
+        let mut chars = text.chars().peekable();
+        let value = try!(parse_value(&mut chars, 0));
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(c) => Err(format!("JSON : unexpected '{}' after the value", c)),
+            None => Ok(value),
+        }
+    }
+
+    /// Create an object from the given members.
+    pub fn object(members: Vec<(&str, Json)>) -> Json {
+        Json::Object(members.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    /// Get the member of the given key, if the value is an object having it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref members) => {
+                members.iter().find(|member| member.0 == key).map(|member| &member.1)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Json::String(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(n) => Some(n),
+            _ => None,
+        }
+    }
+
+    /// Get the value as an unsigned integer, if it is a number without any
+    /// fractional part.
+    pub fn as_usize(&self) -> Option<usize> {
+        match *self {
+            Json::Number(n) if n >= 0.0 && n.fract() == 0.0 && n <= usize::MAX as f64 => {
+                Some(n as usize)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    /// Write the value on a single line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            // JSON has no representation of the infinite values
+            Json::Number(n) if !n.is_finite() => write!(f, "null"),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(ref s) => write_string(f, s),
+            Json::Array(ref values) => {
+                try!(write!(f, "["));
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        try!(write!(f, ","));
+                    }
+                    try!(write!(f, "{}", value));
+                }
+                write!(f, "]")
+            }
+            Json::Object(ref members) => {
+                try!(write!(f, "{{"));
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        try!(write!(f, ","));
+                    }
+                    try!(write_string(f, key));
+                    try!(write!(f, ":{}", value));
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    try!(write!(f, "\""));
+    for c in s.chars() {
+        match c {
+            '"' => try!(write!(f, "\\\"")),
+            '\\' => try!(write!(f, "\\\\")),
+            '\n' => try!(write!(f, "\\n")),
+            '\r' => try!(write!(f, "\\r")),
+            '\t' => try!(write!(f, "\\t")),
+            c if (c as u32) < 0x20 => try!(write!(f, "\\u{:04x}", c as u32)),
+            c => try!(write!(f, "{}", c)),
+        }
+    }
+    write!(f, "\"")
+}
+
+type Input<'a> = Peekable<Chars<'a>>;
+
+fn skip_whitespace(chars: &mut Input) {
+    while let Some(&c) = chars.peek() {
+        if !c.is_whitespace() {
+            break;
+        }
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Input, expected: char) -> Result<(), String> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("JSON : expected '{}', found '{}'", expected, c)),
+        None => Err(format!("JSON : expected '{}', found the end of the text", expected)),
+    }
+}
+
+fn expect_word(chars: &mut Input, word: &str, value: Json) -> Result<Json, String> {
+    for expected in word.chars() {
+        try!(expect(chars, expected));
+    }
+    Ok(value)
+}
+
+/// Parse a value nested in 'depth' arrays or objects.
+fn parse_value(chars: &mut Input, depth: usize) -> Result<Json, String> {
+    skip_whitespace(chars);
+    let next = chars.peek().cloned();
+    if (next == Some('[') || next == Some('{')) && depth >= MAX_JSON_DEPTH {
+        return Err(format!("JSON : nesting too deep"));
+    }
+    match next {
+        Some('n') => expect_word(chars, "null", Json::Null),
+        Some('t') => expect_word(chars, "true", Json::Bool(true)),
+        Some('f') => expect_word(chars, "false", Json::Bool(false)),
+        Some('"') => parse_string(chars).map(Json::String),
+        Some('[') => {
+            chars.next();
+            let mut values = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Ok(Json::Array(values));
+            }
+            loop {
+                values.push(try!(parse_value(chars, depth + 1)));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => (),
+                    Some(']') => return Ok(Json::Array(values)),
+                    _ => return Err(format!("JSON : expected ',' or ']' in array")),
+                }
+            }
+        }
+        Some('{') => {
+            chars.next();
+            let mut members = Vec::new();
+            skip_whitespace(chars);
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Ok(Json::Object(members));
+            }
+            loop {
+                skip_whitespace(chars);
+                let key = try!(parse_string(chars));
+                skip_whitespace(chars);
+                try!(expect(chars, ':'));
+                members.push((key, try!(parse_value(chars, depth + 1))));
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => (),
+                    Some('}') => return Ok(Json::Object(members)),
+                    _ => return Err(format!("JSON : expected ',' or '}}' in object")),
+                }
+            }
+        }
+        Some(c) if c == '-' || c.is_ascii_digit() => {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_digit() || "+-.eE".contains(c) {
+                    number.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            number.parse()
+                .map(Json::Number)
+                .map_err(|_| format!("JSON : invalid number '{}'", number))
+        }
+        Some(c) => Err(format!("JSON : unexpected '{}'", c)),
+        None => Err(format!("JSON : unexpected end of the text")),
+    }
+}
+
+fn parse_string(chars: &mut Input) -> Result<String, String> {
+    try!(expect(chars, '"'));
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => {
+                let c = match chars.next() {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('/') => '/',
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{c}',
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('u') => try!(parse_unicode_escape(chars)),
+                    _ => return Err(format!("JSON : invalid escape sequence in string")),
+                };
+                s.push(c);
+            }
+            Some(c) => s.push(c),
+            None => return Err(format!("JSON : unterminated string")),
+        }
+    }
+}
+
+/// Parse the 4 hexadecimal digits following '\u', and the low surrogate
+/// escape following them if needed.
+fn parse_unicode_escape(chars: &mut Input) -> Result<char, String> {
+    let mut code = try!(parse_hex4(chars));
+    if (0xd800..0xdc00).contains(&code) {
+        try!(expect(chars, '\\'));
+        try!(expect(chars, 'u'));
+        let low = try!(parse_hex4(chars));
+        if !(0xdc00..0xe000).contains(&low) {
+            return Err(format!("JSON : invalid surrogate pair in string"));
+        }
+        code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+    }
+    ::std::char::from_u32(code).ok_or(format!("JSON : invalid character code {:x}", code))
+}
+
+fn parse_hex4(chars: &mut Input) -> Result<u32, String> {
+    let digits: String = chars.take(4).collect();
+    if digits.len() != 4 {
+        return Err(format!("JSON : unterminated unicode escape"));
+    }
+    u32::from_str_radix(&digits, 16)
+        .map_err(|_| format!("JSON : invalid unicode escape '{}'", digits))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Json, MAX_JSON_DEPTH};
+
+    #[test]
+    fn json_values() {
+        let text = r#" {"id": 3, "command" : "load", "ok": [true, false, null],
+                        "grammar": "axiom F\nrule F -> F\"+\u00e9\ud83c\udf3f", "t": -2.5e-1,
+                        "empty": {}} "#;
+        let json = Json::parse(text).ok().unwrap();
+        assert_eq!(json.get("id").and_then(Json::as_usize), Some(3));
+        assert_eq!(json.get("command").and_then(Json::as_str), Some("load"));
+        assert_eq!(json.get("grammar").and_then(Json::as_str),
+                   Some("axiom F\nrule F -> F\"+\u{e9}\u{1f33f}"));
+        assert_eq!(json.get("t").and_then(Json::as_f64), Some(-0.25));
+        assert_eq!(json.get("t").and_then(Json::as_usize), None);
+        assert_eq!(json.get("ok"),
+                   Some(&Json::Array(vec![Json::Bool(true), Json::Bool(false), Json::Null])));
+        assert_eq!(json.get("empty"), Some(&Json::Object(Vec::new())));
+        assert_eq!(json.get("missing"), None);
+
+        // written on a single line, and parsed back
+        let line = json.to_string();
+        assert!(!line.contains('\n'));
+        assert_eq!(Json::parse(&line), Ok(json));
+        assert_eq!(Json::object(vec![("a", Json::Number(1.0)), ("b", Json::String("\t".into()))])
+                       .to_string(),
+                   r#"{"a":1,"b":"\t"}"#);
+
+        for invalid in &["", "{", "[1,]", "{\"a\" 1}", "nul", "\"abc", "1 2", "\"\\x\"", "-"] {
+            assert!(Json::parse(invalid).is_err(), "'{}' parsed", invalid);
+        }
+
+        // too deeply nested values are rejected instead of overflowing the stack
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(Json::parse(&nested(MAX_JSON_DEPTH)).is_ok());
+        assert_eq!(Json::parse(&nested(MAX_JSON_DEPTH + 1)),
+                   Err(format!("JSON : nesting too deep")));
+        assert_eq!(Json::parse(&"[".repeat(100_000)), Err(format!("JSON : nesting too deep")));
+        assert_eq!(Json::parse(&"{\"a\":".repeat(100_000)),
+                   Err(format!("JSON : nesting too deep")));
+    }
+}
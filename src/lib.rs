@@ -1,7 +1,17 @@
-extern crate simple_parallel;
+extern crate rayon;
 
+pub mod compressed;
+pub mod grammar;
+pub mod handle;
+pub mod http;
 pub mod interpret;
+pub mod json;
+pub mod presets;
 pub mod process;
+pub mod protocol;
+pub mod render;
 pub mod rules;
 pub mod state;
+pub mod storage;
+pub mod turtle;
 pub mod view;
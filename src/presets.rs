@@ -0,0 +1,319 @@
+//! Library of classic L-Systems, mostly taken from "The Algorithmic Beauty of
+//! Plants" (Prusinkiewicz & Lindenmayer, 1990).
+//!
+//! All presets are char-based and share the same interpretation conventions:
+//! - the symbols listed in 'Preset::draw' advance the turtle ;
+//! - '+' and '-' turn left and right ;
+//! - '&' and '^' pitch down and up ;
+//! - '\' and '/' roll left and right ;
+//! - '|' turns around ;
+//! - '[' and ']' push and pop the turtle state.
+//! Any other symbol is ignored by the interpreter.
+
+use rules::HashMapRules;
+use state::{LSystem, new_rules_value};
+use interpret::TurtleCommand;
+
+/// Default advance step, in pixels.
+pub const DEFAULT_STEP: f32 = 10.0;
+
+/// Named definition of a classic L-System.
+#[derive(Clone, Debug)]
+pub struct Preset {
+    /// Unique name, as used by the viewer's command line.
+    pub name: &'static str,
+    /// Short human-readable description.
+    pub description: &'static str,
+    /// The initial state.
+    pub axiom: &'static str,
+    /// The production rules, as (symbol, production) pairs.
+    pub productions: &'static [(char, &'static str)],
+    /// The symbols drawing a segment when interpreted.
+    pub draw: &'static str,
+    /// The recommended rotation angle, in degrees.
+    pub angle: f32,
+    /// The recommended number of iterations.
+    pub iterations: u64,
+}
+
+impl Preset {
+    /// Get the axiom as a list of symbols.
+    pub fn axiom(&self) -> Vec<char> {
+        self.axiom.chars().collect()
+    }
+
+    /// Get the interpretation of the given symbol, according to the preset
+    /// conventions and the given advance step.
+    pub fn interpretation(&self, symbol: char, step: f32) -> TurtleCommand {
+        use interpret::TurtleCommand::*;
+        match symbol {
+            _ if self.draw.contains(symbol) => AdvanceBy(step),
+            '+' => RotateBy(self.angle),
+            '-' => RotateBy(-self.angle),
+            '&' => PitchBy(self.angle),
+            '^' => PitchBy(-self.angle),
+            '\\' => RollBy(self.angle),
+            '/' => RollBy(-self.angle),
+            '|' => RotateBy(180.0),
+            '[' => PushState,
+            ']' => PopState,
+            _ => None,
+        }
+    }
+
+    /// Build the rules of the preset with the given advance step.
+    /// Constant symbols are registered with their interpretation only, and the
+    /// symbols without a meaningful interpretation with their production only.
+    pub fn rules(&self, step: f32) -> HashMapRules<char> {
+        let mut rules = HashMapRules::new();
+        for &(symbol, production) in self.productions {
+            rules.set_production(symbol, production.chars().collect());
+        }
+
+        // the constants only need an interpretation
+        let symbols = self.productions.iter().flat_map(|&(_, p)| p.chars());
+        for symbol in self.axiom.chars().chain(symbols) {
+            match self.interpretation(symbol, step) {
+                TurtleCommand::None => (),
+                command => {
+                    rules.set_interpretation(symbol, command);
+                }
+            }
+        }
+
+        rules
+    }
+
+    /// Create the L-System described by the preset, at its initial state.
+    pub fn lsystem(&self, step: f32) -> LSystem<'static, char> {
+        LSystem::new(self.axiom(), new_rules_value(self.rules(step)), None)
+    }
+}
+
+/// All the available presets.
+pub const PRESETS: &'static [Preset] = &[
+    Preset {
+        name: "koch-curve",
+        description: "Quadratic Koch curve",
+        axiom: "F",
+        productions: &[('F', "F+F-F-F+F")],
+        draw: "F",
+        angle: 90.0,
+        iterations: 4,
+    },
+    Preset {
+        name: "koch-snowflake",
+        description: "Koch snowflake",
+        axiom: "F--F--F",
+        productions: &[('F', "F+F--F+F")],
+        draw: "F",
+        angle: 60.0,
+        iterations: 4,
+    },
+    Preset {
+        name: "dragon-curve",
+        description: "Heighway dragon curve",
+        axiom: "FX",
+        productions: &[('X', "X+YF+"), ('Y', "-FX-Y")],
+        draw: "F",
+        angle: 90.0,
+        iterations: 10,
+    },
+    Preset {
+        name: "hilbert-curve",
+        description: "Hilbert space-filling curve",
+        axiom: "A",
+        productions: &[('A', "+BF-AFA-FB+"),
+                       ('B', "-AF+BFB+FA-")],
+        draw: "F",
+        angle: 90.0,
+        iterations: 5,
+    },
+    Preset {
+        name: "peano-curve",
+        description: "Peano space-filling curve",
+        axiom: "X",
+        productions: &[('X', "XFYFX+F+YFXFY-F-XFYFX"),
+                       ('Y', "YFXFY-F-XFYFX+F+YFXFY")],
+        draw: "F",
+        angle: 90.0,
+        iterations: 3,
+    },
+    Preset {
+        name: "gosper-curve",
+        description: "Gosper (flowsnake) curve",
+        axiom: "A",
+        productions: &[('A', "A-B--B+A++AA+B-"),
+                       ('B', "+A-BB--B-A++A+B")],
+        draw: "AB",
+        angle: 60.0,
+        iterations: 4,
+    },
+    Preset {
+        name: "sierpinski-arrowhead",
+        description: "Sierpinski arrowhead curve",
+        axiom: "A",
+        productions: &[('A', "B-A-B"), ('B', "A+B+A")],
+        draw: "AB",
+        angle: 60.0,
+        iterations: 6,
+    },
+    Preset {
+        name: "fractal-plant",
+        description: "Fractal plant",
+        axiom: "X",
+        productions: &[('X', "F+[[X]-X]-F[-FX]+X"),
+                       ('F', "FF")],
+        draw: "F",
+        angle: 25.0,
+        iterations: 6,
+    },
+    Preset {
+        name: "fern",
+        description: "Barnsley-like fern",
+        axiom: "X",
+        productions: &[('X', "F-[[X]+X]+F[+FX]-X"),
+                       ('F', "FF")],
+        draw: "F",
+        angle: 22.5,
+        iterations: 5,
+    },
+    Preset {
+        name: "penrose-tiling",
+        description: "Penrose P3 tiling",
+        axiom: "[7]++[7]++[7]++[7]++[7]",
+        productions: &[('6', "81++91----71[-81----61]++"),
+                       ('7', "+81--91[---61--71]+"),
+                       ('8', "-61++71[+++81++91]-"),
+                       ('9', "--81++++61[+91++++71]--71"),
+                       ('1', "")],
+        draw: "1",
+        angle: 36.0,
+        iterations: 4,
+    },
+    Preset {
+        name: "hilbert-3d",
+        description: "3D Hilbert curve (ABOP fig. 1.27)",
+        axiom: "A",
+        productions: &[('A', "B-F+CFC+F-D&F^D-F+&&CFC+F+B//"),
+                       ('B', "A&F^CFB^F^D^^-F-D^|F^B|FC^F^A//"),
+                       ('C', "|D^|F^B-F+C^F^A&&FA&F^C+F+B^F^D//"),
+                       ('D', "|CFB-F+B|FA&F^A&&FB-F+B|FC//")],
+        draw: "F",
+        angle: 90.0,
+        iterations: 2,
+    },
+    Preset {
+        name: "abop-1.24a",
+        description: "Plant-like structure (ABOP fig. 1.24a)",
+        axiom: "F",
+        productions: &[('F', "F[+F]F[-F]F")],
+        draw: "F",
+        angle: 25.7,
+        iterations: 5,
+    },
+    Preset {
+        name: "abop-1.24b",
+        description: "Plant-like structure (ABOP fig. 1.24b)",
+        axiom: "F",
+        productions: &[('F', "F[+F]F[-F][F]")],
+        draw: "F",
+        angle: 20.0,
+        iterations: 5,
+    },
+    Preset {
+        name: "abop-1.24c",
+        description: "Plant-like structure (ABOP fig. 1.24c)",
+        axiom: "F",
+        productions: &[('F', "FF-[-F+F+F]+[+F-F-F]")],
+        draw: "F",
+        angle: 22.5,
+        iterations: 4,
+    },
+    Preset {
+        name: "abop-1.24d",
+        description: "Plant-like structure (ABOP fig. 1.24d)",
+        axiom: "X",
+        productions: &[('X', "F[+X]F[-X]+X"), ('F', "FF")],
+        draw: "F",
+        angle: 20.0,
+        iterations: 7,
+    },
+    Preset {
+        name: "abop-1.24e",
+        description: "Plant-like structure (ABOP fig. 1.24e)",
+        axiom: "X",
+        productions: &[('X', "F[+X][-X]FX"), ('F', "FF")],
+        draw: "F",
+        angle: 25.7,
+        iterations: 7,
+    },
+];
+
+/// Find a preset by its name.
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|p| p.name == name)
+}
+
+#[cfg(test)]
+mod test {
+    use process::{LProcessor, SimpleProcessor};
+    use interpret::{LInterpreter, SimpleInterpreter, TurtleCommand};
+    use rules::LRules;
+    use super::*;
+
+    fn count(state: &[char], symbol: char) -> usize {
+        state.iter().filter(|&&s| s == symbol).count()
+    }
+
+    #[test]
+    fn presets_lookup() {
+        for (i, preset) in PRESETS.iter().enumerate() {
+            assert!(PRESETS[i + 1..].iter().all(|p| p.name != preset.name));
+            assert_eq!(find(preset.name).unwrap().name, preset.name);
+        }
+        assert!(find("unknown").is_none());
+    }
+
+    #[test]
+    fn presets_constants() {
+        let rules = find("fractal-plant").unwrap().rules(5.0);
+        assert_eq!(rules.production(&'+'), None);
+        assert_eq!(rules.interpretation(&'+'), Some(&TurtleCommand::RotateBy(25.0)));
+        assert_eq!(rules.interpretation(&'-'), Some(&TurtleCommand::RotateBy(-25.0)));
+        assert_eq!(rules.interpretation(&'['), Some(&TurtleCommand::PushState));
+        assert_eq!(rules.interpretation(&'F'), Some(&TurtleCommand::AdvanceBy(5.0)));
+        assert_eq!(rules.production(&'F'), Some(&vec!['F', 'F']));
+
+        let rules = find("hilbert-curve").unwrap().rules(DEFAULT_STEP);
+        assert_eq!(rules.production(&'F'), None);
+        assert_eq!(rules.interpretation(&'F'), Some(&TurtleCommand::AdvanceBy(DEFAULT_STEP)));
+        assert_eq!(rules.interpretation(&'A'), None);
+    }
+
+    #[test]
+    fn presets_growth() {
+        let mut koch = find("koch-snowflake").unwrap().lsystem(DEFAULT_STEP);
+        let mut dragon = find("dragon-curve").unwrap().lsystem(DEFAULT_STEP);
+        for n in 0..5 {
+            assert_eq!(count(koch.state(), 'F'), 3 * 4usize.pow(n));
+            assert_eq!(count(dragon.state(), 'F'), 2usize.pow(n));
+            koch = SimpleProcessor.iterate(&koch).ok().unwrap();
+            dragon = SimpleProcessor.iterate(&dragon).ok().unwrap();
+        }
+    }
+
+    #[test]
+    fn presets_iterate() {
+        for preset in PRESETS {
+            let mut lsystem = preset.lsystem(DEFAULT_STEP);
+            for _ in 0..preset.iterations {
+                lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+            }
+            let commands = SimpleInterpreter.interpret(&lsystem).ok().unwrap();
+            assert!(commands.iter().any(|c| *c == TurtleCommand::AdvanceBy(DEFAULT_STEP)),
+                    "preset '{}' draws nothing",
+                    preset.name);
+        }
+    }
+}
@@ -1,39 +1,133 @@
 use std::sync::{Arc, Mutex};
-use simple_parallel;
+use std::time::Instant;
+use rayon::ThreadPool;
+use rayon::prelude::*;
 
 use state::LSystem;
-use super::{LProcessor, SimpleProcessor};
+use storage::{DiskLSystem, DiskStateWriter, DiskSymbol};
+use super::{LProcessor, SimpleProcessor, new_pool, reconfigure_pool};
+
+/// Minimum chunk size in automatic mode, until the throughput is measured.
+const MIN_AUTO_CHUNK_SIZE: usize = 10_000;
+
+/// Number of chunks per thread aimed at in automatic mode, for load balancing.
+const AUTO_CHUNKS_PER_TASK: usize = 4;
+
+/// Minimum duration of a chunk in automatic mode, in seconds : shorter chunks
+/// would mostly measure the scheduling overhead.
+const MIN_AUTO_CHUNK_TIME: f64 = 0.002;
 
 /// Parallel processor dividing a state into chunks to be individually iterated
 /// within a pool of threads.
 pub struct ChunksProcessor {
-    /// The number of symbols per full chunk.
+    /// The number of symbols per full chunk (the last one used in automatic
+    /// mode).
     chunk_size: usize,
+    /// True if the chunk size is chosen at each iteration.
+    auto: bool,
+    /// The measured throughput of a single task, in produced symbols per
+    /// second (automatic mode only).
+    throughput: Option<f64>,
     /// The thread pool.
-    pool: simple_parallel::Pool,
+    pool: ThreadPool,
 }
 
 impl ChunksProcessor {
     /// Try and create a new 'ChunksProcessor' instance with the given parameters.
     /// Typical values:
     /// - max_tasks : number of CPU logical cores
-    /// - chunks_size : between 100_000 and 1_000_000 symbols per chunk
+    /// - chunks_size : between 100_000 and 1_000_000 symbols per chunk, or see
+    ///   'new_auto'
     pub fn new(max_tasks: usize, chunks_size: usize) -> Result<ChunksProcessor, String> {
-        if max_tasks == 0 {
-            Err(format!("ChunksProcessor::new : invalid maximum tasks number ({})",
-                        max_tasks))
-        } else if chunks_size == 0 {
-            Err(format!("ChunksProcessor::new : invalid chunks size ({})",
-                        chunks_size))
-        } else {
-            Ok(ChunksProcessor {
-                   chunk_size: chunks_size,
-                   pool: simple_parallel::Pool::new(max_tasks),
-               })
+        let pool = try!(new_pool("ChunksProcessor::new", max_tasks, chunks_size));
+        Ok(ChunksProcessor {
+               chunk_size: chunks_size,
+               auto: false,
+               throughput: None,
+               pool: pool,
+           })
+    }
+
+    /// Try and create a new 'ChunksProcessor' instance choosing its chunk size
+    /// at each iteration, from the state length, the number of tasks, the
+    /// average expansion of the rules and the throughput measured during the
+    /// previous iterations (see 'auto_chunk_size').
+    pub fn new_auto(max_tasks: usize) -> Result<ChunksProcessor, String> {
+        let mut processor = try!(ChunksProcessor::new(max_tasks, MIN_AUTO_CHUNK_SIZE));
+        processor.auto = true;
+        Ok(processor)
+    }
+
+    /// Get the number of symbols per full chunk, i.e. the one used by the last
+    /// iteration in automatic mode.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Iterate an L-System stored on disk into its next generation, stored in
+    /// the same directory.
+    /// The chunks are read and iterated in parallel by batches of one chunk per
+    /// task, then written back in chunks of 'chunk_size' symbols, so that the
+    /// memory usage does not depend on the state length.
+    pub fn iterate_on_disk<'a, S>(&mut self,
+                                  lsystem: &DiskLSystem<'a, S>)
+                                  -> Result<DiskLSystem<'a, S>, String>
+        where S: Clone + Eq + Send + Sync + DiskSymbol
+    {
+        let state = lsystem.state();
+        let rules = try!(lsystem.next_rules());
+        if state.is_empty() {
+            return Err(format!("cannot iterate an empty state"));
         }
+        let batch_size = self.pool.current_num_threads();
+        let mut writer = DiskStateWriter::new_in(state.directory());
+        let mut index = 0;
+        while index < state.chunks_number() {
+            let end = (index + batch_size).min(state.chunks_number());
+            let mut batch = Vec::with_capacity(end - index);
+            for i in index..end {
+                batch.push(try!(state.read_chunk(i)));
+            }
+            let results: Vec<Result<Vec<S>, String>> = self.pool.install(|| {
+                batch.par_iter()
+                    .map(|chunk| {
+                        SimpleProcessor::iterate_slice(chunk, rules)
+                            .and_then(|v| SimpleProcessor::decompose_slice(v, rules))
+                    })
+                    .collect()
+            });
+            for result in results {
+                for chunk in try!(result).chunks(self.chunk_size) {
+                    try!(writer.write_chunk(chunk));
+                }
+            }
+            index = end;
+        }
+        lsystem.evolve(writer.finish())
     }
 }
 
+/// Choose the chunk size for a state of the given length : small states are
+/// not split into chunks shorter than 'MIN_AUTO_CHUNK_TIME' (or
+/// 'MIN_AUTO_CHUNK_SIZE' symbols while the throughput is unknown), while big
+/// ones are split into 'AUTO_CHUNKS_PER_TASK' chunks per task.
+/// The throughput is given in produced symbols per second and per task.
+fn auto_chunk_size(state_len: usize,
+                   tasks: usize,
+                   average_expansion: f64,
+                   throughput: Option<f64>)
+                   -> usize {
+    let min_size = match throughput {
+        Some(throughput) => {
+            (throughput * MIN_AUTO_CHUNK_TIME / average_expansion.max(1.0)).ceil() as usize
+        }
+        None => MIN_AUTO_CHUNK_SIZE,
+    };
+    let chunks = (tasks * AUTO_CHUNKS_PER_TASK).max(1);
+    let balanced_size = state_len.div_ceil(chunks);
+    balanced_size.max(min_size).max(1)
+}
+
 impl<S> LProcessor<S> for ChunksProcessor
     where S: Clone + Eq + Send + Sync
 {
@@ -45,6 +139,13 @@ impl<S> LProcessor<S> for ChunksProcessor
         if state_len == 0 {
             return Err(format!("cannot iterate an empty state"));
         }
+        let rules = try!(lsystem.next_rules()).clone();
+        if self.auto {
+            self.chunk_size = auto_chunk_size(state_len,
+                                              self.pool.current_num_threads(),
+                                              rules.average_expansion(),
+                                              self.throughput);
+        }
         let rem = state_len % self.chunk_size;
         let chunks_number = state_len / self.chunk_size +
                             match rem {
@@ -57,12 +158,13 @@ impl<S> LProcessor<S> for ChunksProcessor
         let sub_states = Arc::new(Mutex::new(vec));
 
         // Chunks processing
-        let rules = lsystem.rules().clone();
         let errors = Mutex::new(String::new());
-        let chunks_iter = lsystem.state().chunks(self.chunk_size);
-        self.pool
-            .for_(chunks_iter.enumerate(), |(n, chunk)| {
-                let result: Vec<S> = match SimpleProcessor::iterate_slice(chunk, &rules) {
+        let chunks_iter = lsystem.state().par_chunks(self.chunk_size);
+        let busy_times: Vec<f64> = self.pool.install(|| {
+            chunks_iter.enumerate().map(|(n, chunk)| {
+                let start = Instant::now();
+                let result: Vec<S> = match SimpleProcessor::iterate_slice(chunk, &rules)
+                    .and_then(|v| SimpleProcessor::decompose_slice(v, &rules)) {
                     Ok(v) => v,
                     Err(why) => {
                         let mut error_lock = errors.lock().unwrap();
@@ -70,9 +172,12 @@ impl<S> LProcessor<S> for ChunksProcessor
                         Vec::new()
                     }
                 };
+                let busy_time = start.elapsed().as_secs_f64();
                 let mut chunk_data = sub_states.lock().unwrap();
                 chunk_data[n] = result;
-            });
+                busy_time
+            }).collect()
+        });
 
         // Error handling
         let error_lock = errors.lock().unwrap();
@@ -95,16 +200,50 @@ impl<S> LProcessor<S> for ChunksProcessor
             };
             new_state.extend(chunk_iterated.iter().cloned());
         }
-        Ok(LSystem::<S>::new(new_state, rules, Some(lsystem.iteration() + 1)))
+
+        // Throughput measurement, smoothed over the iterations
+        let busy_time: f64 = busy_times.iter().sum();
+        if self.auto && busy_time > 0.0 {
+            let measured = new_state_size as f64 / busy_time;
+            self.throughput = Some(match self.throughput {
+                Some(throughput) => (throughput + measured) / 2.0,
+                None => measured,
+            });
+        }
+
+        lsystem.evolve(new_state)
+    }
+
+    /// An explicit chunk size disables the automatic mode, even if it is the
+    /// current one.
+    fn configure(&mut self,
+                 max_tasks: Option<usize>,
+                 chunk_size: Option<usize>)
+                 -> Result<(), String> {
+        let new_size = chunk_size.unwrap_or(self.chunk_size);
+        if let Some(pool) = try!(reconfigure_pool("ChunksProcessor::configure",
+                                                  &self.pool,
+                                                  max_tasks,
+                                                  new_size)) {
+            self.pool = pool;
+        }
+        if chunk_size.is_some() {
+            self.chunk_size = new_size;
+            self.auto = false;
+            self.throughput = None;
+        }
+        Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
     use rules::HashMapRules;
-    use state::{LSystem, new_rules_value};
+    use state::{LSystem, RulesTables, TableSchedule, new_rules_value};
     use interpret::TurtleCommand;
     use process::{LProcessor, ChunksProcessor};
+    use storage::DiskLSystem;
+    use super::{auto_chunk_size, MIN_AUTO_CHUNK_SIZE};
 
     #[test]
     fn chunks_processing() {
@@ -122,4 +261,75 @@ mod test {
             lsystem = processor.iterate(&lsystem).ok().unwrap();
         }
     }
+
+    #[test]
+    fn chunks_auto_sizing() {
+        // small states are not split, big ones are balanced between the tasks
+        assert_eq!(auto_chunk_size(500, 4, 2.0, None), MIN_AUTO_CHUNK_SIZE);
+        assert_eq!(auto_chunk_size(1_600_000, 4, 2.0, None), 100_000);
+        // fast productions need bigger chunks to be worth a task
+        assert_eq!(auto_chunk_size(1_600_000, 4, 2.0, Some(1e9)), 1_000_000);
+        assert_eq!(auto_chunk_size(1_600_000, 4, 2.0, Some(1e6)), 100_000);
+
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::None);
+        rules.set_str('B', "A", TurtleCommand::None);
+        let mut lsystem = LSystem::new_with_char("A", new_rules_value(rules));
+        let mut processor = ChunksProcessor::new_auto(4).ok().unwrap();
+        for _ in 0..25 {
+            lsystem = processor.iterate(&lsystem).ok().unwrap();
+            assert!(processor.chunk_size() >= 1);
+        }
+        assert_eq!(lsystem.state().len(), 196418);
+        assert!(ChunksProcessor::new_auto(0).is_err());
+
+        // an explicit chunk size disables the automatic mode
+        assert!(LProcessor::<char>::configure(&mut processor, Some(2), Some(0)).is_err());
+        assert!(LProcessor::<char>::configure(&mut processor, Some(2), Some(1_000)).is_ok());
+        lsystem = processor.iterate(&lsystem).ok().unwrap();
+        assert_eq!(processor.chunk_size(), 1_000);
+        assert_eq!(lsystem.state().len(), 317811);
+
+        // even when it is equal to the current one
+        let mut processor = ChunksProcessor::new_auto(4).ok().unwrap();
+        lsystem = processor.iterate(&lsystem).ok().unwrap();
+        let chunk_size = processor.chunk_size();
+        assert!(LProcessor::<char>::configure(&mut processor, None, Some(chunk_size)).is_ok());
+        assert!(!processor.auto && processor.throughput.is_none());
+        lsystem = processor.iterate(&lsystem).ok().unwrap();
+        assert_eq!(processor.chunk_size(), chunk_size);
+        assert_eq!(lsystem.state().len(), 832040);
+    }
+
+    #[test]
+    fn chunks_processing_on_disk() {
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::None);
+        rules.set_str('B', "A", TurtleCommand::None);
+        rules.set_decomposition_str('B', "DC");
+        rules.set_decomposition_str('C', "");
+        let mut winter = rules.clone();
+        winter.set_str('A', "A", TurtleCommand::None);
+        let tables = RulesTables::new(vec![new_rules_value(rules), new_rules_value(winter)],
+                                      TableSchedule::Cyclic(vec![0, 0, 1]))
+            .ok()
+            .unwrap();
+        let mut lsystem = LSystem::new_with_tables(vec!['A'], tables, None).ok().unwrap();
+        let mut processor = ChunksProcessor::new(2, 100).ok().unwrap();
+        let mut disk = DiskLSystem::store(&lsystem, 100).ok().unwrap();
+
+        for _ in 0..15 {
+            disk = processor.iterate_on_disk(&disk).ok().unwrap();
+            lsystem = processor.iterate(&lsystem).ok().unwrap();
+        }
+        assert_eq!(disk.iteration(), 15);
+        assert_eq!(disk.state().len(), lsystem.state().len());
+        assert!(disk.state().chunks_number() >= disk.state().len().div_ceil(100));
+
+        // loaded back into memory and iterated further
+        let loaded = disk.load().ok().unwrap();
+        assert_eq!(loaded.state(), lsystem.state());
+        assert_eq!(processor.iterate(&loaded).ok().unwrap().state(),
+                   processor.iterate(&lsystem).ok().unwrap().state());
+    }
 }
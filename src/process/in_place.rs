@@ -0,0 +1,211 @@
+use std::mem::MaybeUninit;
+use std::slice;
+use rayon::ThreadPool;
+use rayon::prelude::*;
+
+use rules::LRules;
+use state::LSystem;
+use super::{LProcessor, MAX_DECOMPOSITION_DEPTH, new_pool, reconfigure_pool};
+
+/// Parallel processor writing the iterated chunks of a state directly into the
+/// new state.
+/// The length of each iterated chunk is computed first (the productions being
+/// looked up again in the second pass rather than kept), so that the new state
+/// is allocated only once and filled in place by the work-stealing thread
+/// pool, without any intermediate copy nor lock.
+pub struct InPlaceProcessor {
+    /// The number of symbols per full chunk.
+    chunk_size: usize,
+    pool: ThreadPool,
+}
+
+impl InPlaceProcessor {
+    /// Try and create a new 'InPlaceProcessor' instance with the given parameters.
+    /// Typical values:
+    /// - max_tasks : number of CPU logical cores
+    /// - chunks_size : between 10_000 and 100_000 symbols per chunk
+    pub fn new(max_tasks: usize, chunks_size: usize) -> Result<InPlaceProcessor, String> {
+        let pool = try!(new_pool("InPlaceProcessor::new", max_tasks, chunks_size));
+        Ok(InPlaceProcessor {
+            chunk_size: chunks_size,
+            pool: pool,
+        })
+    }
+}
+
+/// Call the given function with every symbol obtained by decomposing the
+/// successors of the given symbol.
+fn for_each_successor<S, F>(symbol: &S, rules: &LRules<S>, f: &mut F) -> Result<(), String>
+    where S: Eq,
+          F: FnMut(&S) -> Result<(), String>
+{
+    let successors = match rules.production(symbol) {
+        Some(symbols) => &symbols[..],
+        None => slice::from_ref(symbol),
+    };
+    if !rules.has_decompositions() {
+        for s in successors {
+            try!(f(s));
+        }
+        return Ok(());
+    }
+    for s in successors {
+        try!(decompose(s, rules, 0, f));
+    }
+    Ok(())
+}
+
+fn decompose<S, F>(symbol: &S, rules: &LRules<S>, depth: usize, f: &mut F) -> Result<(), String>
+    where S: Eq,
+          F: FnMut(&S) -> Result<(), String>
+{
+    match rules.decomposition(symbol) {
+        Some(symbols) => {
+            if depth >= MAX_DECOMPOSITION_DEPTH {
+                return Err(format!("InPlaceProcessor::iterate : maximum decomposition depth \
+                                    ({}) exceeded",
+                                   MAX_DECOMPOSITION_DEPTH));
+            }
+            for s in symbols {
+                try!(decompose(s, rules, depth + 1, f));
+            }
+            Ok(())
+        }
+        None => f(symbol),
+    }
+}
+
+impl<S> LProcessor<S> for InPlaceProcessor
+    where S: Clone + Eq + Send + Sync
+{
+    fn iterate<'a>(&mut self, lsystem: &LSystem<'a, S>) -> Result<LSystem<'a, S>, String> {
+        if lsystem.state().is_empty() {
+            return Err(format!("cannot iterate an empty state"));
+        }
+        let rules = &***try!(lsystem.next_rules());
+        let chunks: Vec<&[S]> = lsystem.state().chunks(self.chunk_size).collect();
+
+        // iterated length of each chunk
+        let lengths: Vec<Result<usize, String>> = self.pool.install(|| {
+            chunks.par_iter()
+                .map(|chunk| {
+                    let mut length = 0usize;
+                    for symbol in chunk.iter() {
+                        try!(for_each_successor(symbol, rules, &mut |_| {
+                            length += 1;
+                            Ok(())
+                        }));
+                    }
+                    Ok(length)
+                })
+                .collect()
+        });
+        let mut new_state_size = 0usize;
+        let mut chunk_lengths = Vec::with_capacity(lengths.len());
+        for result in lengths {
+            let length = try!(result);
+            new_state_size = try!(new_state_size.checked_add(length)
+                .ok_or(format!("InPlaceProcessor::iterate : usize overflow, state too big for \
+                                Vec")));
+            chunk_lengths.push(length);
+        }
+
+        // in place parallel writing into disjoint slices of the new state
+        let mut new_state: Vec<S> = Vec::with_capacity(new_state_size);
+        {
+            let mut slots: Vec<&mut [MaybeUninit<S>]> = Vec::with_capacity(chunks.len());
+            let mut remaining = &mut new_state.spare_capacity_mut()[..new_state_size];
+            for &length in &chunk_lengths {
+                let (slot, rest) = remaining.split_at_mut(length);
+                slots.push(slot);
+                remaining = rest;
+            }
+            let results: Vec<Result<(), String>> = self.pool.install(|| {
+                chunks.par_iter()
+                    .zip(slots.into_par_iter())
+                    .map(|(chunk, slot)| {
+                        let mut written = 0;
+                        for symbol in chunk.iter() {
+                            try!(for_each_successor(symbol, rules, &mut |s| {
+                                match slot.get_mut(written) {
+                                    Some(cell) => {
+                                        cell.write(s.clone());
+                                        written += 1;
+                                        Ok(())
+                                    }
+                                    None => Err(format!("InPlaceProcessor::iterate : chunk longer \
+                                                         than expected")),
+                                }
+                            }));
+                        }
+                        if written != slot.len() {
+                            return Err(format!("InPlaceProcessor::iterate : chunk shorter than \
+                                                expected"));
+                        }
+                        Ok(())
+                    })
+                    .collect()
+            });
+            for result in results {
+                try!(result);
+            }
+        }
+        // NB : on error, the already written symbols are leaked but never read
+        unsafe {
+            // every slot was written exactly once above
+            new_state.set_len(new_state_size);
+        }
+
+        lsystem.evolve(new_state)
+    }
+
+    fn configure(&mut self,
+                 max_tasks: Option<usize>,
+                 chunk_size: Option<usize>)
+                 -> Result<(), String> {
+        let chunk_size = chunk_size.unwrap_or(self.chunk_size);
+        if let Some(pool) = try!(reconfigure_pool("InPlaceProcessor::configure",
+                                                  &self.pool,
+                                                  max_tasks,
+                                                  chunk_size)) {
+            self.pool = pool;
+        }
+        self.chunk_size = chunk_size;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rules::HashMapRules;
+    use state::{LSystem, new_rules_value};
+    use interpret::TurtleCommand;
+    use process::{LProcessor, SimpleProcessor, InPlaceProcessor};
+
+    #[test]
+    fn in_place_processing() {
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::None);
+        rules.set_str('B', "A", TurtleCommand::None);
+        rules.set_str('C', "ACD", TurtleCommand::None);
+        rules.set_decomposition_str('D', "BB");
+        let mut reference = LSystem::new_with_char("CAB", new_rules_value(rules));
+        let mut lsystem = reference.clone();
+        let mut processor = InPlaceProcessor::new(4, 7).ok().unwrap();
+
+        for _ in 0..15 {
+            reference = SimpleProcessor.iterate(&reference).ok().unwrap();
+            lsystem = processor.iterate(&lsystem).ok().unwrap();
+            assert_eq!(lsystem.iteration(), reference.iteration());
+            assert_eq!(lsystem.state(), reference.state());
+        }
+
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::None);
+        rules.set_decomposition_str('B', "AB");
+        let lsystem = LSystem::new_with_char("A", new_rules_value(rules));
+        assert!(processor.iterate(&lsystem).is_err());
+        assert!(InPlaceProcessor::new(0, 10).is_err());
+        assert!(InPlaceProcessor::new(4, 0).is_err());
+    }
+}
@@ -1,9 +1,14 @@
 mod chunks;
+mod in_place;
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
 
-use super::rules::LRules;
 use super::state::{LSystem, RulesValue};
 
+pub use super::rules::MAX_DECOMPOSITION_DEPTH;
+
 pub use self::chunks::ChunksProcessor;
+pub use self::in_place::InPlaceProcessor;
 
 /// L-System processors are responsible for taking a L-System and evolving it to
 // its next state.
@@ -12,6 +17,78 @@ pub trait LProcessor<S: Clone + Eq> {
     /// its production rules.
     /// Return None if successful, Some(error_string) otherwise.
     fn iterate<'a>(&mut self, lsystem: &LSystem<'a, S>) -> Result<LSystem<'a, S>, String>;
+
+    /// Try and change the maximum number of tasks and/or the number of symbols
+    /// per chunk of a parallel processor, None keeping the current value.
+    /// On failure, the processor is left unchanged.
+    fn configure(&mut self,
+                 max_tasks: Option<usize>,
+                 chunk_size: Option<usize>)
+                 -> Result<(), String> {
+        let _ = (max_tasks, chunk_size);
+        Err(format!("this processor has no parameters"))
+    }
+}
+
+/// Check the parameters of a parallel processor, the given name (e.g.
+/// 'ChunksProcessor::new') prefixing the error messages.
+fn check_parameters(name: &str, max_tasks: usize, chunks_size: usize) -> Result<(), String> {
+    if max_tasks == 0 {
+        Err(format!("{} : invalid maximum tasks number ({})", name, max_tasks))
+    } else if chunks_size == 0 {
+        Err(format!("{} : invalid chunks size ({})", name, chunks_size))
+    } else {
+        Ok(())
+    }
+}
+
+/// Try and create the thread pool of a parallel processor with the given
+/// parameters (see 'check_parameters').
+fn new_pool(name: &str, max_tasks: usize, chunks_size: usize) -> Result<ThreadPool, String> {
+    try!(check_parameters(name, max_tasks, chunks_size));
+    ThreadPoolBuilder::new()
+        .num_threads(max_tasks)
+        .build()
+        .map_err(|why| format!("{} : cannot create the thread pool : {}", name, why))
+}
+
+/// Check the new parameters of a parallel processor using the given pool (see
+/// 'LProcessor::configure'), and try and create its new pool if the maximum
+/// number of tasks is given, None keeping the current pool.
+fn reconfigure_pool(name: &str,
+                    pool: &ThreadPool,
+                    max_tasks: Option<usize>,
+                    chunks_size: usize)
+                    -> Result<Option<ThreadPool>, String> {
+    match max_tasks {
+        Some(max_tasks) => new_pool(name, max_tasks, chunks_size).map(Some),
+        None => check_parameters(name, pool.current_num_threads(), chunks_size).map(|_| None),
+    }
+}
+
+/// Try and create the processor of the given name ('simple', 'chunks' or
+/// 'in-place'), with the given maximum number of tasks (4 by default) and
+/// number of symbols per chunk ('ChunksProcessor' choosing it at each
+/// iteration by default).
+pub fn processor_by_name<S>(name: &str,
+                            max_tasks: Option<usize>,
+                            chunk_size: Option<usize>)
+                            -> Result<Box<LProcessor<S> + Send>, String>
+    where S: Clone + Eq + Send + Sync
+{
+    let max_tasks = max_tasks.unwrap_or(4);
+    match (name, chunk_size) {
+        ("simple", _) => Ok(Box::new(SimpleProcessor)),
+        ("chunks", Some(chunk_size)) => {
+            Ok(Box::new(try!(ChunksProcessor::new(max_tasks, chunk_size))))
+        }
+        ("chunks", None) => Ok(Box::new(try!(ChunksProcessor::new_auto(max_tasks)))),
+        ("in-place", chunk_size) => {
+            let chunk_size = chunk_size.unwrap_or(100_000);
+            Ok(Box::new(try!(InPlaceProcessor::new(max_tasks, chunk_size))))
+        }
+        (name, _) => Err(format!("unknown processor '{}'", name)),
+    }
 }
 
 /// Simple, linear L-System interpreter.
@@ -44,6 +121,51 @@ impl SimpleProcessor {
 
         Ok(result)
     }
+
+    /// Recursively apply the decomposition rules to a freshly iterated slice of
+    /// symbols.
+    /// The symbols are returned as is if none of them can be decomposed.
+    pub fn decompose_slice<'a, S: Clone + Eq>(state: Vec<S>,
+                                              rules: &RulesValue<'a, S>)
+                                              -> Result<Vec<S>, String> {
+        if !rules.has_decompositions() {
+            return Ok(state);
+        }
+        let first = match state.iter().position(|s| rules.decomposition(s).is_some()) {
+            Some(i) => i,
+            None => return Ok(state),
+        };
+        let mut result: Vec<S> = Vec::with_capacity(state.len());
+        result.extend(state[..first].iter().cloned());
+
+        for s in &state[first..] {
+            try!(SimpleProcessor::decompose_symbol(s, rules, 0, &mut result));
+        }
+        result.shrink_to_fit();
+
+        Ok(result)
+    }
+
+    fn decompose_symbol<'a, S: Clone + Eq>(symbol: &S,
+                                           rules: &RulesValue<'a, S>,
+                                           depth: usize,
+                                           result: &mut Vec<S>)
+                                           -> Result<(), String> {
+        match rules.decomposition(symbol) {
+            Some(symbols) => {
+                if depth >= MAX_DECOMPOSITION_DEPTH {
+                    return Err(format!("SimpleProcessor::decompose_slice : maximum \
+                                        decomposition depth ({}) exceeded",
+                                       MAX_DECOMPOSITION_DEPTH));
+                }
+                for s in symbols {
+                    try!(SimpleProcessor::decompose_symbol(s, rules, depth + 1, result));
+                }
+            }
+            None => result.push(symbol.clone()),
+        }
+        Ok(())
+    }
 }
 
 impl<S> LProcessor<S> for SimpleProcessor
@@ -52,11 +174,12 @@ impl<S> LProcessor<S> for SimpleProcessor
     fn iterate<'a>(&mut self, lsystem: &LSystem<'a, S>) -> Result<LSystem<'a, S>, String> {
         // allocate a new state with the worst possible size
         // (may cause overflow one or more iteration(s) earlier with huge states/production rules)
-        let rules = lsystem.rules().clone();
+        let rules = try!(lsystem.next_rules()).clone();
         let new_state = try!(SimpleProcessor::iterate_slice(lsystem.state(), &rules));
+        let new_state = try!(SimpleProcessor::decompose_slice(new_state, &rules));
 
         // return the evolved L-System
-        Ok(LSystem::<S>::new(new_state, rules, Some(lsystem.iteration() + 1)))
+        lsystem.evolve(new_state)
     }
 }
 
@@ -64,7 +187,7 @@ impl<S> LProcessor<S> for SimpleProcessor
 mod test {
     use rules::HashMapRules;
     use state::{LSystem, new_rules_value};
-    use interpret::TurtleCommand;
+    use interpret::{TurtleCommand, LInterpreter, SimpleInterpreter};
     use super::*;
 
     #[test]
@@ -89,4 +212,39 @@ mod test {
             lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
         }
     }
+
+    #[test]
+    fn simple_processing_decomposition() {
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::None);
+        rules.set_decomposition_str('B', "CD");
+        rules.set_decomposition_str('D', "EE");
+        let mut lsystem = LSystem::new_with_char("A", new_rules_value(rules));
+
+        lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        assert_eq!(lsystem.state(), &['A', 'C', 'E', 'E']);
+        lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        assert_eq!(lsystem.state(), &['A', 'C', 'E', 'E', 'C', 'E', 'E']);
+
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::None);
+        rules.set_decomposition_str('B', "AB");
+        let lsystem = LSystem::new_with_char("A", new_rules_value(rules));
+        assert!(SimpleProcessor.iterate(&lsystem).is_err());
+    }
+
+    #[test]
+    fn simple_processing_identity() {
+        // the constants only have an interpretation
+        let mut rules = HashMapRules::new();
+        rules.set_production('F', "F+F".chars().collect());
+        rules.set_interpretation('F', TurtleCommand::AdvanceBy(1.0));
+        rules.set_interpretation('+', TurtleCommand::RotateBy(90.0));
+        let mut lsystem = LSystem::new_with_char("F-F", new_rules_value(rules));
+
+        lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        assert_eq!(lsystem.state().iter().collect::<String>(), "F+F-F+F");
+        let commands = SimpleInterpreter.interpret(&lsystem).ok().unwrap();
+        assert_eq!(commands.len(), 6);
+    }
 }
@@ -0,0 +1,347 @@
+//! JSON-lines protocol of the view worker, so that any process can drive it
+//! through a pair of pipes : each line holds a single JSON object, a request
+//! on the input and a response on the output.
+//!
+//! ```text
+//! {"id": 1, "command": "load", "grammar": "axiom F\nrule F -> F+F\ninterpret F advance 10"}
+//! {"id":1,"session":"default","type":"loaded"}
+//! {"id": 2, "command": "iterate"}
+//! {"id":2,"session":"default","type":"iterated","iteration":1,"length":3}
+//! ```
+//!
+//! A request has an 'id' (any JSON value, repeated in its responses), an
+//! optional 'session' (see 'view::DEFAULT_SESSION') and a 'command', being one
+//! of:
+//! - 'load', with the 'grammar' text definition (see the 'grammar' module) ;
+//! - 'reset', 'iterate', 'interpret', 'list_sessions', 'drop_session' or
+//!   'terminate' ;
+//! - 'interpret_growth', with the time 't' in [0, 1] ;
+//! - 'interpret_stream', with the 'batch_size' ;
+//! - 'set_processor', with the processor 'name' and the optional 'tasks' and
+//!   'chunk_size' (see 'process::processor_by_name') ;
+//! - 'configure_processor', with the optional 'tasks' and 'chunk_size' ;
+//! - 'render', interpreting the current state into the image 'path' ('svg' or
+//!   'png'), of optional 'width' and 'height' (800 by default).
+//!
+//! The 'type' of the responses is one of 'loaded', 'reset', 'iterated' (with
+//! the 'iteration' and the state 'length'), 'interpreted' (with the
+//! 'commands'), 'sessions' (with the 'sessions' names), 'session_dropped',
+//! 'processor_changed', 'rendered' (with the 'path' and the number of
+//! 'segments'), 'terminated' or 'error' (with the 'message').
+//! A stream is answered with a 'stream' response followed by 'batch' ones
+//! (with their 'commands') and a final 'stream_end' or 'error' one.
+//! The turtle commands are written as in the grammar definitions, e.g.
+//! "advance 10" or "push".
+
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc::{Sender, Receiver};
+
+use grammar::{self, Grammar};
+use interpret::TurtleCommand;
+use json::Json;
+use process;
+use render;
+use turtle::{Turtle, TurtleConfig};
+use view::{MessageFromViewer, MessageToViewer, Request, Response, SessionId, DEFAULT_SESSION};
+
+/// The default size of the rendered images, in pixels.
+const DEFAULT_RENDER_SIZE: u32 = 800;
+
+/// Command of a protocol request.
+pub enum HostCommand {
+    /// Command executed by the worker thread.
+    Worker(MessageFromViewer<char>),
+    /// Interpret the current state and save it as an image of the given path
+    /// and size.
+    Render(String, u32, u32),
+}
+
+/// Get a member of the request, failing if it is missing.
+fn member<'a>(request: &'a Json, key: &str) -> Result<&'a Json, String> {
+    request.get(key).ok_or(format!("missing '{}'", key))
+}
+
+/// Get a string member of the request, failing if it is missing.
+fn string_member<'a>(request: &'a Json, key: &str) -> Result<&'a str, String> {
+    try!(member(request, key)).as_str().ok_or(format!("invalid '{}'", key))
+}
+
+/// Get an optional unsigned integer member of the request.
+fn optional_usize(request: &Json, key: &str) -> Result<Option<usize>, String> {
+    match request.get(key) {
+        Some(&Json::Null) | None => Ok(None),
+        Some(value) => value.as_usize().map(Some).ok_or(format!("invalid '{}'", key)),
+    }
+}
+
+/// Try and read the session and the command of the given request.
+pub fn parse_request(request: &Json) -> Result<(SessionId, HostCommand), String> {
+    use view::MessageFromViewer::*;
+    let session = match request.get("session") {
+        Some(session) => try!(session.as_str().ok_or(format!("invalid 'session'"))),
+        None => DEFAULT_SESSION,
+    };
+    let command = try!(string_member(request, "command"));
+    let message = match command {
+        "load" => {
+            let grammar = try!(Grammar::parse(try!(string_member(request, "grammar"))));
+            LoadLSystem(grammar.axiom, Box::new(grammar.rules))
+        }
+        "reset" => ResetLSystem,
+        "iterate" => Iterate,
+        "interpret" => Interpret,
+        "interpret_growth" => {
+            let t = try!(try!(member(request, "t")).as_f64().ok_or(format!("invalid 't'")));
+            InterpretGrowth(t as f32)
+        }
+        "interpret_stream" => {
+            match try!(optional_usize(request, "batch_size")) {
+                Some(batch_size) if batch_size > 0 => InterpretStream(batch_size),
+                _ => return Err(format!("invalid 'batch_size'")),
+            }
+        }
+        "list_sessions" => ListSessions,
+        "drop_session" => DropSession,
+        "set_processor" => {
+            let name = try!(string_member(request, "name"));
+            let tasks = try!(optional_usize(request, "tasks"));
+            let chunk_size = try!(optional_usize(request, "chunk_size"));
+            SetProcessor(try!(process::processor_by_name(name, tasks, chunk_size)))
+        }
+        "configure_processor" => {
+            ConfigureProcessor(try!(optional_usize(request, "tasks")),
+                               try!(optional_usize(request, "chunk_size")))
+        }
+        "terminate" => Terminate,
+        "render" => {
+            let path = try!(string_member(request, "path"));
+            let size = |key| {
+                optional_usize(request, key).and_then(|size| match size {
+                    Some(size) if size == 0 || size > u32::MAX as usize => {
+                        Err(format!("invalid '{}'", key))
+                    }
+                    size => Ok(size.map_or(DEFAULT_RENDER_SIZE, |size| size as u32)),
+                })
+            };
+            let (width, height) = (try!(size("width")), try!(size("height")));
+            let command = HostCommand::Render(path.to_string(), width, height);
+            return Ok((session.to_string(), command));
+        }
+        command => return Err(format!("unknown command '{}'", command)),
+    };
+    Ok((session.to_string(), HostCommand::Worker(message)))
+}
+
+fn commands_to_json(commands: &[TurtleCommand]) -> Json {
+    Json::Array(commands.iter().map(|c| Json::String(grammar::command_to_text(c))).collect())
+}
+
+/// Create the response to the request of the given identifier and session,
+/// of the given type and with the given members.
+pub fn response(id: &Json, session: &str, kind: &str, members: Vec<(&str, Json)>) -> Json {
+    let mut response = vec![("id", id.clone()),
+                            ("session", Json::String(session.to_string())),
+                            ("type", Json::String(kind.to_string()))];
+    response.extend(members);
+    Json::object(response)
+}
+
+/// Create the response of the given worker message.
+/// NB : the batches of an 'InterpretationStream' are not included.
+pub fn message_to_json(id: &Json, session: &str, message: &MessageToViewer) -> Json {
+    use view::MessageToViewer::*;
+    let (kind, members) = match *message {
+        LoadingFinished => ("loaded", vec![]),
+        ResettingFinised => ("reset", vec![]),
+        IterationFinished(iteration, len) => {
+            ("iterated",
+             vec![("iteration", Json::Number(iteration as f64)),
+                  ("length", Json::Number(len as f64))])
+        }
+        InterpretationFinished(ref commands) => {
+            ("interpreted", vec![("commands", commands_to_json(commands))])
+        }
+        InterpretationStream(_) => ("stream", vec![]),
+        SessionList(ref sessions) => {
+            let names = sessions.iter().map(|name| Json::String(name.clone())).collect();
+            ("sessions", vec![("sessions", Json::Array(names))])
+        }
+        SessionDropped => ("session_dropped", vec![]),
+        ProcessorChanged => ("processor_changed", vec![]),
+        InterpreterChanged => ("interpreter_changed", vec![]),
+        Terminated => ("terminated", vec![]),
+        Error(ref why) => ("error", vec![("message", Json::String(why.clone()))]),
+    };
+    response(id, session, kind, members)
+}
+
+fn write_line<W: Write>(output: &mut W, json: &Json) -> Result<(), String> {
+    writeln!(output, "{}", json)
+        .and_then(|_| output.flush())
+        .map_err(|why| format!("cannot write the response : {}", why))
+}
+
+/// Execute a single request, and write its responses.
+/// Return false if the worker has terminated.
+fn execute<W: Write>(id: &Json,
+                     session: SessionId,
+                     command: HostCommand,
+                     output: &mut W,
+                     tx: &Sender<Request<char>>,
+                     rx: &Receiver<Response>)
+                     -> Result<bool, String> {
+    use view::MessageToViewer::*;
+    let (message, target) = match command {
+        HostCommand::Worker(message) => (message, None),
+        HostCommand::Render(path, width, height) => {
+            (MessageFromViewer::Interpret, Some((path, width, height)))
+        }
+    };
+    let request = Request::in_session(&session, message);
+    let request_id = request.id;
+    try!(tx.send(request).map_err(|_| format!("the worker thread has stopped")));
+    let message = loop {
+        let response = try!(rx.recv().map_err(|_| format!("the worker thread has stopped")));
+        // responses to requests of other clients are ignored
+        if response.id == request_id {
+            break response.message;
+        }
+    };
+    match (message, target) {
+        (InterpretationFinished(commands), Some((path, width, height))) => {
+            let segments = Turtle::new(TurtleConfig::default()).execute(&commands);
+            let result = render::save_image(Path::new(&path), &segments, width, height, None);
+            let json = match result {
+                Ok(()) => {
+                    response(id,
+                             &session,
+                             "rendered",
+                             vec![("path", Json::String(path)),
+                                  ("segments", Json::Number(segments.len() as f64))])
+                }
+                Err(why) => message_to_json(id, &session, &Error(why)),
+            };
+            try!(write_line(output, &json));
+        }
+        (InterpretationStream(stream), _) => {
+            try!(write_line(output, &response(id, &session, "stream", vec![])));
+            for batch in stream {
+                match batch {
+                    Ok(commands) => {
+                        let commands = vec![("commands", commands_to_json(&commands))];
+                        try!(write_line(output, &response(id, &session, "batch", commands)));
+                    }
+                    Err(why) => {
+                        try!(write_line(output, &message_to_json(id, &session, &Error(why))));
+                        return Ok(true);
+                    }
+                }
+            }
+            try!(write_line(output, &response(id, &session, "stream_end", vec![])));
+        }
+        (Terminated, _) => {
+            try!(write_line(output, &message_to_json(id, &session, &Terminated)));
+            return Ok(false);
+        }
+        (message, _) => try!(write_line(output, &message_to_json(id, &session, &message))),
+    }
+    Ok(true)
+}
+
+/// Read the requests from the given input and write their responses to the
+/// given output, one per line, until the input ends or the worker thread of
+/// the given channels terminates.
+/// The requests are executed in order ; an invalid one is answered with an
+/// 'error' response.
+pub fn serve<R: BufRead, W: Write>(input: R,
+                                   output: &mut W,
+                                   tx: &Sender<Request<char>>,
+                                   rx: &Receiver<Response>)
+                                   -> Result<(), String> {
+    for line in input.lines() {
+        let line = try!(line.map_err(|why| format!("cannot read the request : {}", why)));
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = Json::parse(&line);
+        let id = request.as_ref().ok().and_then(|r| r.get("id")).cloned().unwrap_or(Json::Null);
+        match request.and_then(|request| parse_request(&request)) {
+            Ok((session, command)) => {
+                if !try!(execute(&id, session, command, output, tx, rx)) {
+                    break;
+                }
+            }
+            Err(why) => {
+                let error = MessageToViewer::Error(why);
+                try!(write_line(output, &message_to_json(&id, DEFAULT_SESSION, &error)));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs;
+
+    use json::Json;
+    use process::{LProcessor, SimpleProcessor};
+    use interpret::{LInterpreter, SimpleInterpreter};
+    use view;
+    use super::serve;
+
+    #[test]
+    fn json_lines_protocol() {
+        let processor: Box<LProcessor<char> + Send> = Box::new(SimpleProcessor);
+        let interpreter: Box<LInterpreter<char> + Send> = Box::new(SimpleInterpreter);
+        let (tx, rx, worker) = view::spawn_worker(RefCell::new(processor),
+                                                  RefCell::new(interpreter));
+        let image = env::temp_dir().join(format!("rustlsystem-protocol-{}.svg",
+                                                 ::std::process::id()));
+        let grammar = "axiom F\nrule F -> F+F\ninterpret F advance 10\ninterpret + rotate 90";
+        let requests = format!(r#"{{"id": 1, "command": "iterate"}}
+{{"id": "a", "command": "load", "grammar": {}}}
+{{"id": 2, "command": "iterate"}}
+
+{{"id": 3, "command": "interpret"}}
+{{"id": 4, "command": "interpret_stream", "batch_size": 2}}
+{{"id": 5, "command": "render", "path": {}, "width": 100}}
+{{"id": 6, "command": "set_processor", "name": "chunks", "tasks": 2}}
+{{"id": 7, "session": "other", "command": "list_sessions"}}
+{{"id": 8, "command": "unknown"}}
+not json
+{{"id": 9, "command": "terminate"}}
+{{"id": 10, "command": "reset"}}
+"#,
+                               Json::String(grammar.to_string()),
+                               Json::String(image.to_string_lossy().into_owned()));
+        let mut output = Vec::new();
+        assert!(serve(requests.as_bytes(), &mut output, &tx, &rx).is_ok());
+        assert!(worker.join().is_ok());
+
+        let output = String::from_utf8(output).unwrap();
+        let responses: Vec<Json> = output.lines().map(|l| Json::parse(l).ok().unwrap()).collect();
+        let types: Vec<&str> =
+            responses.iter().map(|r| r.get("type").and_then(Json::as_str).unwrap()).collect();
+        assert_eq!(types,
+                   vec!["error", "loaded", "iterated", "interpreted", "stream", "batch", "batch",
+                        "stream_end", "rendered", "processor_changed", "sessions", "error",
+                        "error", "terminated"]);
+        assert_eq!(responses[1].get("id"), Some(&Json::String("a".to_string())));
+        assert_eq!(responses[2].get("length").and_then(Json::as_usize), Some(3));
+        assert_eq!(responses[3].get("commands").map(|c| c.to_string()),
+                   Some(r#"["advance 10","rotate 90","advance 10"]"#.to_string()));
+        assert_eq!(responses[8].get("segments").and_then(Json::as_usize), Some(2));
+        assert_eq!(responses[10].get("session").and_then(Json::as_str), Some("other"));
+        assert_eq!(responses[10].get("sessions").map(|s| s.to_string()),
+                   Some(r#"["default"]"#.to_string()));
+        assert_eq!(responses[12].get("id"), Some(&Json::Null));
+
+        let svg = fs::read_to_string(&image).ok().unwrap();
+        let _ = fs::remove_file(&image);
+        assert!(svg.contains("width=\"100\""));
+    }
+}
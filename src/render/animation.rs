@@ -0,0 +1,157 @@
+//! Animations of successive generations, each generation being a frame.
+//! All the frames share the framing of the last one, so that the figure does
+//! not jump around between frames.
+
+use std::path::{Path, PathBuf};
+
+use turtle::Segment;
+use super::{Bounds, Viewport, save_image};
+use super::png::PngOptions;
+use super::svg::{self, SvgOptions};
+
+/// Scale each frame around the turtle origin so that its size matches the size
+/// of the last frame, compensating for the segments getting shorter (or
+/// longer) relatively to the whole figure at each generation.
+pub fn normalize(frames: &mut [Vec<Segment>]) {
+    let size = |segments: &[Segment]| {
+        Bounds::of_segments(segments).map_or(0.0, |b| b.width().max(b.height()))
+    };
+    let target = match frames.last() {
+        Some(last) => size(last),
+        None => return,
+    };
+    for frame in frames.iter_mut() {
+        let frame_size = size(frame);
+        if frame_size > 0.0 && target > 0.0 {
+            let factor = target / frame_size;
+            for segment in frame.iter_mut() {
+                segment.from = segment.from * factor;
+                segment.to = segment.to * factor;
+            }
+        }
+    }
+}
+
+/// Get the viewport shared by all the frames : the one fitting the last frame
+/// into an image of the given size.
+pub fn framing(frames: &[Vec<Segment>], width: u32, height: u32, margin: f32) -> Viewport {
+    let bounds = frames.last()
+        .and_then(|last| Bounds::of_segments(last))
+        .unwrap_or(Bounds::new(0.0, 0.0));
+    Viewport::fit(&bounds, width as f32, height as f32, margin)
+}
+
+/// Get the path of the frame of the given index, i.e. the given path with the
+/// zero-padded index appended to its file stem ('growth.png' gives
+/// 'growth_000.png', 'growth_001.png'...).
+pub fn frame_path(path: &Path, index: usize) -> PathBuf {
+    let stem = path.file_stem().map_or(String::new(), |s| s.to_string_lossy().into_owned());
+    let mut name = format!("{}_{:03}", stem, index);
+    if let Some(extension) = path.extension() {
+        name.push('.');
+        name.push_str(&extension.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+/// Render the given frames as an animated SVG document, each frame being
+/// displayed for the given duration in seconds.
+pub fn to_animated_svg(frames: &[Vec<Segment>],
+                       options: &SvgOptions,
+                       frame_duration: f32)
+                       -> String {
+    let viewport = framing(frames, options.width, options.height, options.margin);
+    let n = frames.len();
+    let mut document = svg::svg_header(options);
+    for (i, frame) in frames.iter().enumerate() {
+        let (start, end) = (i as f32 / n as f32, (i + 1) as f32 / n as f32);
+        let (values, key_times) = if i == 0 {
+            ("visible;hidden".to_string(), format!("0;{:.4}", end))
+        } else {
+            ("hidden;visible;hidden".to_string(), format!("0;{:.4};{:.4}", start, end))
+        };
+        document.push_str(&format!("<path visibility=\"hidden\" fill=\"none\" stroke=\"{}\" \
+                                    stroke-width=\"{}\" stroke-linecap=\"round\" d=\"{}\">\n\
+                                    <animate attributeName=\"visibility\" values=\"{}\" \
+                                    keyTimes=\"{}\" calcMode=\"discrete\" dur=\"{}s\" \
+                                    repeatCount=\"indefinite\"/>\n</path>\n",
+                                   options.stroke,
+                                   options.stroke_width,
+                                   svg::path_data(frame, &viewport),
+                                   values,
+                                   key_times,
+                                   frame_duration * n as f32));
+    }
+    document.push_str("</svg>\n");
+    document
+}
+
+/// Save the given frames either as an animated SVG document or as a sequence
+/// of PNG images (see 'frame_path'), depending on the file extension.
+/// Return the number of files written.
+pub fn save_animation(path: &Path,
+                      frames: &[Vec<Segment>],
+                      width: u32,
+                      height: u32,
+                      frame_duration: f32)
+                      -> Result<usize, String> {
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    match extension.as_ref().map(|e| &e[..]) {
+        Some("svg") => {
+            let options = SvgOptions {
+                width: width,
+                height: height,
+                ..SvgOptions::default()
+            };
+            try!(svg::write_svg(path, &to_animated_svg(frames, &options, frame_duration)));
+            Ok(1)
+        }
+        Some("png") => {
+            let viewport = framing(frames, width, height, PngOptions::default().margin);
+            for (i, frame) in frames.iter().enumerate() {
+                try!(save_image(&frame_path(path, i), frame, width, height, Some(&viewport)));
+            }
+            Ok(frames.len())
+        }
+        _ => {
+            Err(format!("unknown animation format for {} (expected .svg or .png)",
+                        path.display()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use turtle::{Segment, Vector3};
+    use render::svg::SvgOptions;
+    use super::*;
+
+    fn line(length: f32) -> Vec<Segment> {
+        vec![Segment {
+                 from: Vector3::new(0.0, 0.0, 0.0),
+                 to: Vector3::new(length, 0.0, 0.0),
+             }]
+    }
+
+    #[test]
+    fn animation_frames() {
+        let mut frames = vec![line(1.0), line(3.0), vec![], line(9.0)];
+        let viewport = framing(&frames, 100, 100, 5.0);
+        assert_eq!(viewport.project(&Vector3::new(9.0, 0.0, 0.0)), (95.0, 50.0));
+        normalize(&mut frames);
+        assert_eq!(frames[0], line(9.0));
+        assert_eq!(frames[1], line(9.0));
+        assert!(frames[2].is_empty());
+
+        assert_eq!(frame_path(Path::new("out/growth.png"), 7),
+                   PathBuf::from("out/growth_007.png"));
+
+        let svg = to_animated_svg(&frames, &SvgOptions::default(), 0.5);
+        assert_eq!(svg.matches("<animate ").count(), 4);
+        assert!(svg.contains("values=\"visible;hidden\" keyTimes=\"0;0.2500\""));
+        assert!(svg.contains("values=\"hidden;visible;hidden\" keyTimes=\"0;0.7500;1.0000\""));
+        assert!(svg.contains("dur=\"2s\""));
+    }
+}
@@ -0,0 +1,604 @@
+//! Rendering of deep generations without deriving them, for resolution
+//! independent zooming.
+//! The drawing of the expansion of a symbol after 'n' iterations is a rigid
+//! motion of the turtle, whatever its position and orientation : its final
+//! turtle state and a conservative bounding ball are computed once in the
+//! turtle frame for every symbol and iteration, by composing the ones of its
+//! successors. The derivation tree is then expanded lazily, skipping the
+//! subtrees whose ball lies outside the viewport and drawing the ones whose
+//! ball is smaller than a pixel threshold as a single segment.
+//! The motions are composed in double precision, and the visible segments are
+//! given in raster coordinates (see 'DeepViewport').
+//! NB : the tropism and jitter of the turtle are not applied, and each
+//! expansion must either push and pop the turtle state in matching pairs, or
+//! be a single push or pop (like the usual '[' and ']' symbols).
+
+use std::ops::{Add, Mul, Sub};
+use std::slice;
+
+use compressed::{derivation_alphabet, symbol_index};
+use interpret::{TurtleCommand, for_each_interpretation};
+use rules::LRules;
+use state::LSystem;
+use turtle::{Segment, Turtle, TurtleConfig, TurtleState, Vector3};
+use super::{Bounds, Viewport};
+
+/// Point or vector in double precision : the coordinates of deep generations
+/// are far beyond the precision of 'f32' (e.g. 3^25 units for the Koch curve).
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Point {
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl Point {
+    fn new(x: f64, y: f64, z: f64) -> Point {
+        Point { x: x, y: y, z: z }
+    }
+
+    fn of(v: &Vector3) -> Point {
+        Point::new(v.x as f64, v.y as f64, v.z as f64)
+    }
+
+    fn dot(&self, other: &Point) -> f64 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    fn norm(&self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    fn normalized(&self) -> Point {
+        *self * (1.0 / self.norm())
+    }
+}
+
+impl Add for Point {
+    type Output = Point;
+
+    fn add(self, other: Point) -> Point {
+        Point::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Point {
+    type Output = Point;
+
+    fn sub(self, other: Point) -> Point {
+        Point::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f64> for Point {
+    type Output = Point;
+
+    fn mul(self, factor: f64) -> Point {
+        Point::new(self.x * factor, self.y * factor, self.z * factor)
+    }
+}
+
+/// Turtle state in double precision.
+#[derive(Clone, Debug)]
+struct Frame {
+    position: Point,
+    heading: Point,
+    left: Point,
+    up: Point,
+}
+
+impl Frame {
+    fn new() -> Frame {
+        Frame::of(&TurtleState::new())
+    }
+
+    fn of(state: &TurtleState) -> Frame {
+        Frame {
+            position: Point::of(&state.position),
+            heading: Point::of(&state.heading),
+            left: Point::of(&state.left),
+            up: Point::of(&state.up),
+        }
+    }
+}
+
+/// Viewport of a deep rendering : like 'Viewport', but in double precision so
+/// that any part of a deep generation can be zoomed in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeepViewport {
+    /// Raster units per drawing unit.
+    pub scale: f64,
+    /// The drawing point mapped to the raster origin.
+    pub origin_x: f64,
+    pub origin_y: f64,
+}
+
+impl DeepViewport {
+    /// Get the double precision equivalent of the given viewport.
+    pub fn of(viewport: &Viewport) -> DeepViewport {
+        DeepViewport {
+            scale: viewport.scale as f64,
+            origin_x: viewport.origin_x as f64,
+            origin_y: viewport.origin_y as f64,
+        }
+    }
+
+    /// Zoom by the given factor around the given raster point.
+    pub fn zoom(&mut self, factor: f64, x: f64, y: f64) {
+        self.origin_x += x / self.scale * (1.0 - 1.0 / factor);
+        self.origin_y -= y / self.scale * (1.0 - 1.0 / factor);
+        self.scale *= factor;
+    }
+
+    /// Pan by the given raster offset.
+    pub fn pan(&mut self, dx: f64, dy: f64) {
+        self.origin_x += dx / self.scale;
+        self.origin_y -= dy / self.scale;
+    }
+
+    fn project(&self, point: &Point) -> (f64, f64) {
+        ((point.x - self.origin_x) * self.scale, (self.origin_y - point.y) * self.scale)
+    }
+
+    /// Get the raster segment between the given points.
+    fn segment(&self, from: &Point, to: &Point) -> Segment {
+        let (from, to) = (self.project(from), self.project(to));
+        Segment {
+            from: Vector3::new(from.0 as f32, from.1 as f32, 0.0),
+            to: Vector3::new(to.0 as f32, to.1 as f32, 0.0),
+        }
+    }
+}
+
+/// Ball containing a drawing, in the turtle frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Ball {
+    center: Point,
+    radius: f64,
+}
+
+impl Ball {
+    /// Get the smallest ball containing both balls.
+    fn merge(&self, other: &Ball) -> Ball {
+        let distance = (other.center - self.center).norm();
+        if distance + other.radius <= self.radius {
+            *self
+        } else if distance + self.radius <= other.radius {
+            *other
+        } else {
+            let radius = (distance + self.radius + other.radius) / 2.0;
+            Ball {
+                center: self.center +
+                        (other.center - self.center) * ((radius - self.radius) / distance),
+                radius: radius,
+            }
+        }
+    }
+
+    fn of_segment(segment: &Segment) -> Ball {
+        let (from, to) = (Point::of(&segment.from), Point::of(&segment.to));
+        Ball {
+            center: (from + to) * 0.5,
+            radius: (to - from).norm() / 2.0,
+        }
+    }
+}
+
+/// Effect of the expansion of a symbol on the turtle.
+#[derive(Clone, Debug)]
+enum Effect {
+    PushState,
+    PopState,
+    /// The final turtle state when starting from the initial one, and the ball
+    /// containing the drawing, if any.
+    Move(Frame, Option<Ball>),
+}
+
+/// Express the given vector of the initial turtle frame in the given frame.
+fn to_frame(frame: &Frame, v: &Point) -> Point {
+    // initial frame : heading = +Y, left = -X, up = +Z
+    frame.heading * v.y + frame.left * (-v.x) + frame.up * v.z
+}
+
+/// Get the frame obtained by applying the given relative motion (expressed
+/// from the initial turtle state) to the given frame.
+fn compose(frame: &Frame, motion: &Frame) -> Frame {
+    // the rounding errors of the axes would otherwise grow exponentially with
+    // the generation, each effect being composed from several others
+    let heading = to_frame(frame, &motion.heading).normalized();
+    let left = to_frame(frame, &motion.left);
+    let left = (left - heading * heading.dot(&left)).normalized();
+    let up = to_frame(frame, &motion.up);
+    let up = (up - heading * heading.dot(&up) - left * left.dot(&up)).normalized();
+    Frame {
+        position: frame.position + to_frame(frame, &motion.position),
+        heading: heading,
+        left: left,
+        up: up,
+    }
+}
+
+fn transform_ball(frame: &Frame, ball: &Ball) -> Ball {
+    Ball {
+        center: frame.position + to_frame(frame, &ball.center),
+        radius: ball.radius,
+    }
+}
+
+fn merge(ball: Option<Ball>, other: Ball) -> Option<Ball> {
+    Some(match ball {
+        Some(ball) => ball.merge(&other),
+        None => other,
+    })
+}
+
+fn unbalanced() -> String {
+    format!("DeepRenderer : unbalanced push/pop in the expansion of a symbol")
+}
+
+/// Lazy renderer of a generation of an L-System, see the module documentation.
+pub struct DeepRenderer<S> {
+    /// The distinct symbols.
+    alphabet: Vec<S>,
+    /// The decomposed successors of each symbol.
+    successors: Vec<Vec<usize>>,
+    axiom: Vec<usize>,
+    /// The segments drawn by each symbol, in the turtle frame.
+    leaves: Vec<Vec<(Point, Point)>>,
+    /// The effect of each symbol, for each number of iterations.
+    effects: Vec<Vec<Effect>>,
+}
+
+impl<S: Clone + Eq> DeepRenderer<S> {
+    /// Create the renderer of the generation obtained by deriving the given
+    /// axiom the given number of times with the given rules.
+    pub fn new(axiom: &[S],
+               rules: &LRules<S>,
+               generation: u64)
+               -> Result<DeepRenderer<S>, String> {
+        let (alphabet, successors) = try!(derivation_alphabet(axiom, rules));
+
+        let mut leaves = Vec::with_capacity(alphabet.len());
+        let mut level = Vec::with_capacity(alphabet.len());
+        for symbol in &alphabet {
+            let mut commands = Vec::new();
+            try!(for_each_interpretation(slice::from_ref(symbol), rules, &mut |_, command| {
+                commands.push(command.clone());
+                true
+            }));
+            let (segments, effect) = try!(DeepRenderer::<S>::leaf_effect(&commands));
+            leaves.push(segments);
+            level.push(effect);
+        }
+
+        let mut effects = vec![level];
+        for _ in 0..generation {
+            let mut next = Vec::with_capacity(alphabet.len());
+            {
+                let previous = effects.last().unwrap();
+                for symbol_successors in &successors {
+                    let children: Vec<&Effect> =
+                        symbol_successors.iter().map(|&s| &previous[s]).collect();
+                    next.push(try!(DeepRenderer::<S>::sequence_effect(&children)));
+                }
+            }
+            effects.push(next);
+        }
+
+        let axiom = axiom.iter().map(|s| symbol_index(&alphabet, s).unwrap()).collect();
+        Ok(DeepRenderer {
+            alphabet: alphabet,
+            successors: successors,
+            axiom: axiom,
+            leaves: leaves,
+            effects: effects,
+        })
+    }
+
+    /// Create the renderer of the generation obtained by deriving the state of
+    /// the given L-System the given number of times.
+    /// NB : table L-Systems are not supported.
+    pub fn from_lsystem(lsystem: &LSystem<S>,
+                        iterations: u64)
+                        -> Result<DeepRenderer<S>, String> {
+        if lsystem.tables().is_some() {
+            return Err(format!("DeepRenderer : table L-Systems are not supported"));
+        }
+        DeepRenderer::new(lsystem.state(), &***lsystem.rules(), iterations)
+    }
+
+    /// Get the number of distinct symbols of the derivation.
+    pub fn alphabet_len(&self) -> usize {
+        self.alphabet.len()
+    }
+
+    /// Get conservative bounds of the whole drawing, or None if nothing is
+    /// drawn.
+    pub fn bounds(&self) -> Option<Bounds> {
+        let generation = self.effects.len() - 1;
+        let mut state = Frame::new();
+        let mut stack = Vec::new();
+        let mut bounds: Option<Bounds> = None;
+        for &symbol in &self.axiom {
+            match self.effects[generation][symbol] {
+                Effect::PushState => stack.push(state.clone()),
+                Effect::PopState => {
+                    if let Some(s) = stack.pop() {
+                        state = s;
+                    }
+                }
+                Effect::Move(ref motion, ref ball) => {
+                    if let Some(ref ball) = *ball {
+                        let ball = transform_ball(&state, ball);
+                        let (center, radius) = (ball.center, ball.radius);
+                        let mut ball_bounds = Bounds::new((center.x - radius) as f32,
+                                                          (center.y - radius) as f32);
+                        ball_bounds.include(&Vector3::new((center.x + radius) as f32,
+                                                          (center.y + radius) as f32,
+                                                          0.0));
+                        bounds = Some(match bounds {
+                            Some(b) => b.union(&ball_bounds),
+                            None => ball_bounds,
+                        });
+                    }
+                    state = compose(&state, motion);
+                }
+            }
+        }
+        bounds
+    }
+
+    /// Call the given function with the segments visible in a raster of the
+    /// given size through the given viewport, in raster coordinates, the
+    /// subtrees whose projected size is below the given threshold (in pixels)
+    /// being drawn as a single segment from their starting point to their
+    /// ending point.
+    pub fn render<F>(&self,
+                     viewport: &DeepViewport,
+                     width: f32,
+                     height: f32,
+                     threshold: f32,
+                     f: &mut F)
+        where F: FnMut(Segment)
+    {
+        let generation = self.effects.len() - 1;
+        let mut state = Frame::new();
+        let mut stack = Vec::new();
+        for &symbol in &self.axiom {
+            self.visit(symbol,
+                       generation,
+                       &mut state,
+                       &mut stack,
+                       &(viewport, width, height, threshold),
+                       f);
+        }
+    }
+
+    /// Get the segments visible in a raster of the given size through the given
+    /// viewport, in raster coordinates (see 'render').
+    pub fn segments(&self,
+                    viewport: &DeepViewport,
+                    width: f32,
+                    height: f32,
+                    threshold: f32)
+                    -> Vec<Segment> {
+        let mut segments = Vec::new();
+        self.render(viewport, width, height, threshold, &mut |s| segments.push(s));
+        segments
+    }
+
+    fn visit<F>(&self,
+                symbol: usize,
+                iterations: usize,
+                state: &mut Frame,
+                stack: &mut Vec<Frame>,
+                view: &(&DeepViewport, f32, f32, f32),
+                f: &mut F)
+        where F: FnMut(Segment)
+    {
+        let (viewport, width, height, threshold) = *view;
+        let (motion, ball) = match self.effects[iterations][symbol] {
+            Effect::PushState => return stack.push(state.clone()),
+            Effect::PopState => {
+                if let Some(s) = stack.pop() {
+                    *state = s;
+                }
+                return;
+            }
+            Effect::Move(ref motion, ref ball) => (motion, ball),
+        };
+        let end = compose(state, motion);
+        if let Some(ref ball) = *ball {
+            let ball = transform_ball(state, ball);
+            let (x, y) = viewport.project(&ball.center);
+            let radius = ball.radius * viewport.scale;
+            // distance from the projected center to the raster, with a pixel margin
+            let dx = (-x).max(x - width as f64).max(0.0);
+            let dy = (-y).max(y - height as f64).max(0.0);
+            let visible = dx * dx + dy * dy <= (radius + 1.0) * (radius + 1.0);
+            if visible && 2.0 * radius < threshold as f64 {
+                f(viewport.segment(&state.position, &end.position));
+            } else if visible && iterations == 0 {
+                for &(from, to) in &self.leaves[symbol] {
+                    f(viewport.segment(&(state.position + to_frame(state, &from)),
+                                       &(state.position + to_frame(state, &to))));
+                }
+            } else if visible {
+                for &successor in &self.successors[symbol] {
+                    self.visit(successor, iterations - 1, state, stack, view, f);
+                }
+                return;
+            }
+        }
+        *state = end;
+    }
+
+    /// Get the segments and the effect of the given commands.
+    fn leaf_effect(commands: &[TurtleCommand]) -> Result<(Vec<(Point, Point)>, Effect), String> {
+        match commands {
+            [TurtleCommand::PushState] => return Ok((Vec::new(), Effect::PushState)),
+            [TurtleCommand::PopState] => return Ok((Vec::new(), Effect::PopState)),
+            _ => (),
+        }
+        let mut turtle = Turtle::new(TurtleConfig::default());
+        let mut depth = 0usize;
+        let mut segments = Vec::new();
+        for command in commands {
+            match *command {
+                TurtleCommand::PushState => depth += 1,
+                TurtleCommand::PopState => {
+                    depth = try!(depth.checked_sub(1).ok_or_else(unbalanced));
+                }
+                _ => (),
+            }
+            if let Some(segment) = turtle.apply(command) {
+                segments.push(segment);
+            }
+        }
+        if depth != 0 {
+            return Err(unbalanced());
+        }
+        let ball = segments.iter().fold(None, |ball, s| merge(ball, Ball::of_segment(s)));
+        let segments = segments.iter().map(|s| (Point::of(&s.from), Point::of(&s.to))).collect();
+        Ok((segments, Effect::Move(Frame::of(turtle.state()), ball)))
+    }
+
+    /// Get the effect of the given sequence of effects.
+    fn sequence_effect(effects: &[&Effect]) -> Result<Effect, String> {
+        if effects.len() == 1 {
+            return Ok(effects[0].clone());
+        }
+        let mut state = Frame::new();
+        let mut stack = Vec::new();
+        let mut ball = None;
+        for effect in effects {
+            match **effect {
+                Effect::PushState => stack.push(state.clone()),
+                Effect::PopState => state = try!(stack.pop().ok_or_else(unbalanced)),
+                Effect::Move(ref motion, ref child_ball) => {
+                    if let Some(ref child_ball) = *child_ball {
+                        ball = merge(ball, transform_ball(&state, child_ball));
+                    }
+                    state = compose(&state, motion);
+                }
+            }
+        }
+        if !stack.is_empty() {
+            return Err(unbalanced());
+        }
+        Ok(Effect::Move(state, ball))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use presets;
+    use process::{LProcessor, SimpleProcessor};
+    use render::clip_line;
+    use rules::HashMapRules;
+    use state::{LSystem, new_rules_value};
+    use interpret::TurtleCommand;
+    use turtle::{Segment, Turtle, TurtleConfig, Vector3};
+    use super::*;
+
+    fn near(a: &Segment, b: &Segment) -> bool {
+        (a.from - b.from).norm() < 1e-2 && (a.to - b.to).norm() < 1e-2
+    }
+
+    /// Get the given segment in raster coordinates.
+    fn project(viewport: &Viewport, segment: &Segment) -> Segment {
+        let (from, to) = (viewport.project(&segment.from), viewport.project(&segment.to));
+        Segment {
+            from: Vector3::new(from.0, from.1, 0.0),
+            to: Vector3::new(to.0, to.1, 0.0),
+        }
+    }
+
+    #[test]
+    fn deep_rendering() {
+        let lsystem = presets::find("fractal-plant").unwrap().lsystem(1.0);
+        let mut derived = lsystem.clone();
+        for _ in 0..4 {
+            derived = SimpleProcessor.iterate(&derived).ok().unwrap();
+        }
+        let expected = Turtle::new(TurtleConfig::default()).execute_lsystem(&derived).ok().unwrap();
+
+        let renderer = DeepRenderer::from_lsystem(&lsystem, 4).ok().unwrap();
+        let bounds = renderer.bounds().unwrap();
+        let whole = Bounds::of_segments(&expected).unwrap();
+        assert!(bounds.min_x <= whole.min_x && bounds.max_x >= whole.max_x);
+        assert!(bounds.min_y <= whole.min_y && bounds.max_y >= whole.max_y);
+
+        // everything visible, full detail
+        let viewport = Viewport::fit(&bounds, 200.0, 200.0, 0.0);
+        let segments = renderer.segments(&DeepViewport::of(&viewport), 200.0, 200.0, 0.0);
+        assert_eq!(segments.len(), expected.len());
+        assert!(segments.iter().zip(&expected).all(|(a, b)| near(a, &project(&viewport, b))));
+
+        // zoomed in : the full-detail segments, culled near the viewport, are
+        // kept, reproducing exactly the visible ones
+        let is_visible = |s: &Segment| {
+            clip_line((s.from.x, s.from.y), (s.to.x, s.to.y), 200.0, 200.0).is_some()
+        };
+        for &factor in &[8.0, 64.0] {
+            let mut zoomed = viewport;
+            zoomed.zoom(factor, 100.0, 100.0);
+            let segments = renderer.segments(&DeepViewport::of(&zoomed), 200.0, 200.0, 0.0);
+            assert!(segments.len() < expected.len());
+            let full: Vec<Segment> = expected.iter().map(|s| project(&zoomed, s)).collect();
+            assert!(segments.iter().all(|a| full.iter().any(|b| near(a, b))));
+            let visible: Vec<&Segment> = full.iter().filter(|s| is_visible(s)).collect();
+            let kept: Vec<&Segment> = segments.iter().filter(|s| is_visible(s)).collect();
+            assert!(!visible.is_empty());
+            assert_eq!(kept.len(), visible.len());
+            assert!(kept.iter().zip(&visible).all(|(a, b)| near(a, b)));
+        }
+
+        // level of detail : the zoomed out drawing is simplified
+        let segments = renderer.segments(&DeepViewport::of(&viewport), 200.0, 200.0, 1e6);
+        assert!(!segments.is_empty() && segments.len() < expected.len());
+    }
+
+    #[test]
+    fn deep_zoom() {
+        let koch = presets::find("koch-curve").unwrap().lsystem(1.0);
+        let renderer = DeepRenderer::from_lsystem(&koch, 25).ok().unwrap();
+        let bounds = renderer.bounds().unwrap();
+        assert!(bounds.width() > 3f32.powi(24) && bounds.width() < 3f32.powi(26));
+
+        // a 100x100 window at the start of the curve
+        let viewport = DeepViewport {
+            scale: 1.0,
+            origin_x: -50.0,
+            origin_y: 50.0,
+        };
+        let segments = renderer.segments(&viewport, 100.0, 100.0, 0.0);
+        assert!(!segments.is_empty() && segments.len() < 10_000);
+        let segments = renderer.segments(&viewport, 100.0, 100.0, 4.0);
+        assert!(!segments.is_empty() && segments.len() < 1_000);
+
+        // a 100x100 window at the end of the curve, 3^25 units away from the
+        // origin : the unit segments must keep their length
+        let end = match renderer.effects[25][renderer.axiom[0]] {
+            Effect::Move(ref frame, _) => frame.position,
+            _ => panic!("the axiom must move the turtle"),
+        };
+        assert!(end.norm() > 3f64.powi(24));
+        let viewport = DeepViewport {
+            scale: 1.0,
+            origin_x: end.x - 50.0,
+            origin_y: end.y + 50.0,
+        };
+        let segments = renderer.segments(&viewport, 100.0, 100.0, 0.0);
+        assert!(!segments.is_empty() && segments.len() < 10_000);
+        assert!(segments.iter().all(|s| ((s.to - s.from).norm() - 1.0).abs() < 1e-3));
+        let last = Vector3::new(50.0, 50.0, 0.0);
+        assert!(segments.iter().any(|s| (s.to - last).norm() < 1e-3));
+
+        let mut rules = HashMapRules::new();
+        rules.set_str('F', "F[", TurtleCommand::AdvanceBy(1.0));
+        rules.set_str('[', "[", TurtleCommand::PushState);
+        let lsystem = LSystem::new_with_char("F", new_rules_value(rules));
+        assert!(DeepRenderer::from_lsystem(&lsystem, 2).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+//! Rasterization helpers shared by the different renderers.
+//! All renderers draw the XY projection of the turtle segments.
+
+pub mod animation;
+pub mod deep;
+pub mod png;
+pub mod svg;
+mod text;
+
+pub use self::text::{TextCanvas, CanvasMode};
+
+use std::f32;
+use std::path::Path;
+
+use turtle::{Segment, Vector3};
+
+/// Axis-aligned bounding box of a 2D drawing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+impl Bounds {
+    /// Create the bounding box of a single point.
+    pub fn new(x: f32, y: f32) -> Bounds {
+        Bounds {
+            min_x: x,
+            min_y: y,
+            max_x: x,
+            max_y: y,
+        }
+    }
+
+    /// Get the bounding box of the given segments, or None if there are none.
+    pub fn of_segments(segments: &[Segment]) -> Option<Bounds> {
+        let mut bounds = match segments.first() {
+            Some(s) => Bounds::new(s.from.x, s.from.y),
+            None => return None,
+        };
+        for segment in segments {
+            bounds.include(&segment.from);
+            bounds.include(&segment.to);
+        }
+        Some(bounds)
+    }
+
+    /// Grow the bounding box to include the given point.
+    pub fn include(&mut self, point: &Vector3) {
+        self.min_x = self.min_x.min(point.x);
+        self.min_y = self.min_y.min(point.y);
+        self.max_x = self.max_x.max(point.x);
+        self.max_y = self.max_y.max(point.y);
+    }
+
+    /// Get the smallest bounding box containing both bounding boxes.
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            min_x: self.min_x.min(other.min_x),
+            min_y: self.min_y.min(other.min_y),
+            max_x: self.max_x.max(other.max_x),
+            max_y: self.max_y.max(other.max_y),
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.max_x - self.min_x
+    }
+
+    pub fn height(&self) -> f32 {
+        self.max_y - self.min_y
+    }
+
+    /// Get the center point of the bounding box.
+    pub fn center(&self) -> (f32, f32) {
+        ((self.min_x + self.max_x) / 2.0, (self.min_y + self.max_y) / 2.0)
+    }
+}
+
+/// Transformation from drawing coordinates to raster coordinates, the raster
+/// Y axis pointing downward.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// Raster units per drawing unit.
+    pub scale: f32,
+    /// The drawing point mapped to the raster origin.
+    pub origin_x: f32,
+    pub origin_y: f32,
+}
+
+impl Viewport {
+    /// Create the viewport fitting the given bounds into a raster of the given
+    /// size with the given margin, while keeping the aspect ratio.
+    pub fn fit(bounds: &Bounds, width: f32, height: f32, margin: f32) -> Viewport {
+        let available_width = (width - 2.0 * margin).max(1.0);
+        let available_height = (height - 2.0 * margin).max(1.0);
+        // a flat drawing is only constrained by its other dimension
+        let scale_x = if bounds.width() > 0.0 {
+            available_width / bounds.width()
+        } else {
+            f32::INFINITY
+        };
+        let scale_y = if bounds.height() > 0.0 {
+            available_height / bounds.height()
+        } else {
+            f32::INFINITY
+        };
+        let scale = match scale_x.min(scale_y) {
+            scale if scale.is_finite() => scale,
+            _ => 1.0,
+        };
+        let (center_x, center_y) = bounds.center();
+        Viewport {
+            scale: scale,
+            origin_x: center_x - width / 2.0 / scale,
+            origin_y: center_y + height / 2.0 / scale,
+        }
+    }
+
+    /// Zoom by the given factor around the given raster point.
+    pub fn zoom(&mut self, factor: f32, x: f32, y: f32) {
+        self.origin_x += x / self.scale * (1.0 - 1.0 / factor);
+        self.origin_y -= y / self.scale * (1.0 - 1.0 / factor);
+        self.scale *= factor;
+    }
+
+    /// Pan by the given raster offset.
+    pub fn pan(&mut self, dx: f32, dy: f32) {
+        self.origin_x += dx / self.scale;
+        self.origin_y -= dy / self.scale;
+    }
+
+    /// Project the given point onto the raster.
+    pub fn project(&self, point: &Vector3) -> (f32, f32) {
+        ((point.x - self.origin_x) * self.scale, (self.origin_y - point.y) * self.scale)
+    }
+
+    /// Get the drawing area visible in a raster of the given size.
+    pub fn visible_bounds(&self, width: f32, height: f32) -> Bounds {
+        Bounds {
+            min_x: self.origin_x,
+            min_y: self.origin_y - height / self.scale,
+            max_x: self.origin_x + width / self.scale,
+            max_y: self.origin_y,
+        }
+    }
+}
+
+/// Clip the given raster line to the [0, width] x [0, height] rectangle
+/// (Liang-Barsky algorithm), or return None if it lies outside.
+pub fn clip_line(from: (f32, f32),
+                 to: (f32, f32),
+                 width: f32,
+                 height: f32)
+                 -> Option<((f32, f32), (f32, f32))> {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let mut t0 = 0f32;
+    let mut t1 = 1f32;
+    for &(p, q) in &[(-dx, from.0), (dx, width - from.0), (-dy, from.1), (dy, height - from.1)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let r = q / p;
+            if p < 0.0 {
+                t0 = t0.max(r);
+            } else {
+                t1 = t1.min(r);
+            }
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    Some(((from.0 + t0 * dx, from.1 + t0 * dy), (from.0 + t1 * dx, from.1 + t1 * dy)))
+}
+
+/// Call the given function with every pixel of the given raster line, clipped
+/// to a raster of the given size.
+pub fn for_each_pixel<F>(from: (f32, f32), to: (f32, f32), width: usize, height: usize, mut f: F)
+    where F: FnMut(usize, usize)
+{
+    let (from, to) = match clip_line(from, to, width as f32, height as f32) {
+        Some(line) => line,
+        None => return,
+    };
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let steps = dx.abs().max(dy.abs()).ceil().max(1.0);
+    let (step_x, step_y) = (dx / steps, dy / steps);
+    let (mut x, mut y) = from;
+    let mut last = None;
+    for _ in 0..(steps as usize + 1) {
+        let pixel = (x.floor() as usize, y.floor() as usize);
+        if pixel.0 < width && pixel.1 < height && last != Some(pixel) {
+            f(pixel.0, pixel.1);
+            last = Some(pixel);
+        }
+        x += step_x;
+        y += step_y;
+    }
+}
+
+/// Save the given segments as an image of the given size, projected with the
+/// given viewport or fitted into the image if None.
+/// The format ('svg' or 'png') is chosen from the file extension.
+pub fn save_image(path: &Path,
+                  segments: &[Segment],
+                  width: u32,
+                  height: u32,
+                  viewport: Option<&Viewport>)
+                  -> Result<(), String> {
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+    match extension.as_ref().map(|e| &e[..]) {
+        Some("svg") => {
+            let options = svg::SvgOptions {
+                width: width,
+                height: height,
+                ..svg::SvgOptions::default()
+            };
+            let svg = match viewport {
+                Some(viewport) => svg::to_svg_with_viewport(segments, viewport, &options),
+                None => svg::to_svg(segments, &options),
+            };
+            svg::write_svg(path, &svg)
+        }
+        Some("png") => {
+            let options = png::PngOptions {
+                width: width,
+                height: height,
+                ..png::PngOptions::default()
+            };
+            let png = match viewport {
+                Some(viewport) => png::to_png_with_viewport(segments, viewport, &options),
+                None => png::to_png(segments, &options),
+            };
+            png::write_png(path, &png)
+        }
+        _ => Err(format!("unknown image format for {} (expected .svg or .png)", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use turtle::{Segment, Vector3};
+    use super::*;
+
+    #[test]
+    fn bounds_and_viewport() {
+        let segments = [Segment {
+                            from: Vector3::new(0.0, 0.0, 0.0),
+                            to: Vector3::new(10.0, 5.0, 0.0),
+                        },
+                        Segment {
+                            from: Vector3::new(-10.0, 0.0, 3.0),
+                            to: Vector3::new(0.0, -5.0, 0.0),
+                        }];
+        let bounds = Bounds::of_segments(&segments).unwrap();
+        assert_eq!(bounds,
+                   Bounds {
+                       min_x: -10.0,
+                       min_y: -5.0,
+                       max_x: 10.0,
+                       max_y: 5.0,
+                   });
+        assert!(Bounds::of_segments(&[]).is_none());
+
+        let mut viewport = Viewport::fit(&bounds, 100.0, 100.0, 10.0);
+        assert_eq!(viewport.scale, 4.0);
+        assert_eq!(viewport.project(&Vector3::new(-10.0, 5.0, 0.0)), (10.0, 30.0));
+        assert_eq!(viewport.project(&Vector3::new(0.0, 0.0, 0.0)), (50.0, 50.0));
+
+        viewport.zoom(2.0, 50.0, 50.0);
+        assert_eq!(viewport.project(&Vector3::new(0.0, 0.0, 0.0)), (50.0, 50.0));
+        assert_eq!(viewport.project(&Vector3::new(10.0, 0.0, 0.0)), (130.0, 50.0));
+        viewport.pan(10.0, -10.0);
+        assert_eq!(viewport.project(&Vector3::new(0.0, 0.0, 0.0)), (40.0, 60.0));
+    }
+
+    #[test]
+    fn line_clipping() {
+        assert_eq!(clip_line((-10.0, 5.0), (20.0, 5.0), 10.0, 10.0),
+                   Some(((0.0, 5.0), (10.0, 5.0))));
+        assert_eq!(clip_line((-10.0, -5.0), (20.0, -5.0), 10.0, 10.0), None);
+        assert_eq!(clip_line((1.0, 1.0), (2.0, 3.0), 10.0, 10.0),
+                   Some(((1.0, 1.0), (2.0, 3.0))));
+
+        let mut pixels = Vec::new();
+        for_each_pixel((0.5, 0.5), (3.5, 0.5), 3, 3, |x, y| pixels.push((x, y)));
+        assert_eq!(pixels, vec![(0, 0), (1, 0), (2, 0)]);
+    }
+}
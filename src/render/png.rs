@@ -0,0 +1,239 @@
+//! Minimal PNG rendering, without any external dependency : the image data is
+//! stored uncompressed in the zlib stream, which is valid but bigger than
+//! what an optimizing encoder would produce.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use turtle::Segment;
+use super::{Bounds, Viewport, for_each_pixel};
+
+/// RGB color.
+pub type Color = [u8; 3];
+
+/// Options of the PNG rendering.
+#[derive(Clone, Debug)]
+pub struct PngOptions {
+    /// The image size, in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// The minimum blank space around the drawing, in pixels.
+    pub margin: f32,
+    pub stroke: Color,
+    pub background: Color,
+}
+
+impl Default for PngOptions {
+    fn default() -> PngOptions {
+        PngOptions {
+            width: 800,
+            height: 800,
+            margin: 10.0,
+            stroke: [0, 0, 0],
+            background: [255, 255, 255],
+        }
+    }
+}
+
+impl PngOptions {
+    /// Get the viewport fitting the given bounds into the image.
+    pub fn fit(&self, bounds: &Bounds) -> Viewport {
+        Viewport::fit(bounds, self.width as f32, self.height as f32, self.margin)
+    }
+}
+
+/// RGB raster image.
+pub struct Raster {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Raster {
+    /// Create a new raster of the given size, filled with the given color.
+    pub fn new(width: usize, height: usize, background: Color) -> Raster {
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for _ in 0..width * height {
+            pixels.extend_from_slice(&background);
+        }
+        Raster {
+            width: width,
+            height: height,
+            pixels: pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Get the color of the pixel at the given position.
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        let i = (y * self.width + x) * 3;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    /// Set the pixel at the given position, if inside the raster.
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let i = (y * self.width + x) * 3;
+        self.pixels[i..i + 3].copy_from_slice(&color);
+    }
+
+    /// Draw the given segments, projected with the given viewport.
+    pub fn draw_segments(&mut self, segments: &[Segment], viewport: &Viewport, color: Color) {
+        let (width, height) = (self.width, self.height);
+        for segment in segments {
+            for_each_pixel(viewport.project(&segment.from),
+                           viewport.project(&segment.to),
+                           width,
+                           height,
+                           |x, y| self.set(x, y, color));
+        }
+    }
+
+    /// Encode the raster as a PNG file.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut png = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&u32_be(self.width as u32));
+        header.extend_from_slice(&u32_be(self.height as u32));
+        // 8 bits per channel, RGB, deflate, adaptive filtering, no interlacing
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        write_chunk(&mut png, b"IHDR", &header);
+
+        // each scanline starts with its filter type (none)
+        let mut scanlines = Vec::with_capacity((self.width * 3 + 1) * self.height);
+        for row in self.pixels.chunks(self.width * 3) {
+            scanlines.push(0);
+            scanlines.extend_from_slice(row);
+        }
+        write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+/// Render the given segments as a PNG file, fitted into the image.
+pub fn to_png(segments: &[Segment], options: &PngOptions) -> Vec<u8> {
+    let bounds = Bounds::of_segments(segments).unwrap_or(Bounds::new(0.0, 0.0));
+    to_png_with_viewport(segments, &options.fit(&bounds), options)
+}
+
+/// Render the given segments as a PNG file, projected with the given viewport.
+pub fn to_png_with_viewport(segments: &[Segment],
+                            viewport: &Viewport,
+                            options: &PngOptions)
+                            -> Vec<u8> {
+    let mut raster = Raster::new(options.width as usize,
+                                 options.height as usize,
+                                 options.background);
+    raster.draw_segments(segments, viewport, options.stroke);
+    raster.encode()
+}
+
+/// Write the given PNG file content to the given file.
+pub fn write_png(path: &Path, png: &[u8]) -> Result<(), String> {
+    File::create(path)
+        .and_then(|mut file| file.write_all(png))
+        .map_err(|why| format!("cannot write {} : {}", path.display(), why))
+}
+
+fn u32_be(value: u32) -> [u8; 4] {
+    [(value >> 24) as u8, (value >> 16) as u8, (value >> 8) as u8, value as u8]
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&u32_be(data.len() as u32));
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&u32_be(crc));
+}
+
+/// Wrap the given data into a zlib stream made of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        zlib.push(if last { 1 } else { 0 });
+        zlib.extend_from_slice(&[len as u8, (len >> 8) as u8, !len as u8, (!len >> 8) as u8]);
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&u32_be(adler32(data)));
+    zlib
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod test {
+    use turtle::{Segment, Vector3};
+    use super::*;
+
+    #[test]
+    fn png_encoding() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        let zlib = zlib_stored(&[7; 70000]);
+        assert_eq!(zlib.len(), 2 + 5 + 65535 + 5 + 4465 + 4);
+        assert_eq!(&zlib[2..7], &[0, 0xff, 0xff, 0, 0]);
+
+        let segments = [Segment {
+                            from: Vector3::new(0.0, 0.0, 0.0),
+                            to: Vector3::new(0.0, 10.0, 0.0),
+                        }];
+        let options = PngOptions {
+            width: 5,
+            height: 12,
+            margin: 1.0,
+            ..PngOptions::default()
+        };
+        let png = to_png(&segments, &options);
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]);
+        assert_eq!(&png[12..24], b"IHDR\x00\x00\x00\x05\x00\x00\x00\x0c");
+        assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+
+        let mut raster = Raster::new(5, 12, [255, 255, 255]);
+        raster.draw_segments(&segments, &options.fit(&Bounds::of_segments(&segments).unwrap()),
+                             [0, 0, 0]);
+        assert_eq!(raster.get(2, 6), [0, 0, 0]);
+        assert_eq!(raster.get(0, 6), [255, 255, 255]);
+    }
+}
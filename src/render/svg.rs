@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+use turtle::Segment;
+use super::{Bounds, Viewport};
+
+/// Options of the SVG rendering.
+#[derive(Clone, Debug)]
+pub struct SvgOptions {
+    /// The image size, in pixels.
+    pub width: u32,
+    pub height: u32,
+    /// The minimum blank space around the drawing, in pixels.
+    pub margin: f32,
+    /// The stroke color, as any SVG color.
+    pub stroke: String,
+    pub stroke_width: f32,
+    /// The optional background color.
+    pub background: Option<String>,
+}
+
+impl Default for SvgOptions {
+    fn default() -> SvgOptions {
+        SvgOptions {
+            width: 800,
+            height: 800,
+            margin: 10.0,
+            stroke: "black".to_string(),
+            stroke_width: 1.0,
+            background: Some("white".to_string()),
+        }
+    }
+}
+
+impl SvgOptions {
+    /// Get the viewport fitting the given bounds into the image.
+    pub fn fit(&self, bounds: &Bounds) -> Viewport {
+        Viewport::fit(bounds, self.width as f32, self.height as f32, self.margin)
+    }
+}
+
+/// Render the given segments as an SVG document, fitted into the image.
+pub fn to_svg(segments: &[Segment], options: &SvgOptions) -> String {
+    let bounds = Bounds::of_segments(segments).unwrap_or(Bounds::new(0.0, 0.0));
+    to_svg_with_viewport(segments, &options.fit(&bounds), options)
+}
+
+/// Render the given segments as an SVG document, projected with the given
+/// viewport.
+pub fn to_svg_with_viewport(segments: &[Segment],
+                            viewport: &Viewport,
+                            options: &SvgOptions)
+                            -> String {
+    let mut svg = svg_header(options);
+    svg.push_str(&format!("<path fill=\"none\" stroke=\"{}\" stroke-width=\"{}\" \
+                           stroke-linecap=\"round\" d=\"{}\"/>\n",
+                          options.stroke,
+                          options.stroke_width,
+                          path_data(segments, viewport)));
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Write the given SVG document to the given file.
+pub fn write_svg(path: &Path, svg: &str) -> Result<(), String> {
+    File::create(path)
+        .and_then(|mut file| file.write_all(svg.as_bytes()))
+        .map_err(|why| format!("cannot write {} : {}", path.display(), why))
+}
+
+/// Get the opening 'svg' tag and the background of an SVG document.
+pub fn svg_header(options: &SvgOptions) -> String {
+    let mut svg = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<svg \
+                           xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+                           viewBox=\"0 0 {0} {1}\">\n",
+                          options.width,
+                          options.height);
+    if let Some(ref background) = options.background {
+        svg.push_str(&format!("<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>\n",
+                              background));
+    }
+    svg
+}
+
+/// Get the SVG path data of the given segments, consecutive segments being
+/// joined into polylines.
+pub fn path_data(segments: &[Segment], viewport: &Viewport) -> String {
+    let mut data = String::new();
+    let mut last = None;
+    for segment in segments {
+        let from = viewport.project(&segment.from);
+        let to = viewport.project(&segment.to);
+        if last != Some(segment.from) {
+            data.push_str(&format!("M{:.2} {:.2}", from.0, from.1));
+        }
+        data.push_str(&format!("L{:.2} {:.2}", to.0, to.1));
+        last = Some(segment.to);
+    }
+    data
+}
+
+#[cfg(test)]
+mod test {
+    use turtle::{Segment, Vector3};
+    use super::*;
+
+    #[test]
+    fn svg_rendering() {
+        let segments = [Segment {
+                            from: Vector3::new(0.0, 0.0, 0.0),
+                            to: Vector3::new(0.0, 10.0, 0.0),
+                        },
+                        Segment {
+                            from: Vector3::new(0.0, 10.0, 0.0),
+                            to: Vector3::new(10.0, 10.0, 0.0),
+                        },
+                        Segment {
+                            from: Vector3::new(0.0, 0.0, 0.0),
+                            to: Vector3::new(10.0, 0.0, 0.0),
+                        }];
+        let options = SvgOptions {
+            width: 120,
+            height: 120,
+            background: None,
+            ..SvgOptions::default()
+        };
+        let svg = to_svg(&segments, &options);
+        assert!(svg.contains("width=\"120\" height=\"120\""));
+        assert!(!svg.contains("<rect"));
+        assert!(svg.contains("d=\"M10.00 110.00L10.00 10.00L110.00 10.00M10.00 110.00L110.00 \
+                              110.00\""));
+        assert!(svg.ends_with("</svg>\n"));
+    }
+}
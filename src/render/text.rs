@@ -0,0 +1,152 @@
+use std::char;
+
+use turtle::Segment;
+use super::{Viewport, for_each_pixel};
+
+/// Character set used by a 'TextCanvas'.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CanvasMode {
+    /// Unicode braille patterns, with 2x4 dots per character.
+    Braille,
+    /// Plain ASCII, with 1x2 dots per character.
+    Ascii,
+}
+
+impl CanvasMode {
+    /// Get the number of dots per character, horizontally and vertically.
+    pub fn resolution(&self) -> (usize, usize) {
+        match *self {
+            CanvasMode::Braille => (2, 4),
+            CanvasMode::Ascii => (1, 2),
+        }
+    }
+}
+
+/// Monochrome canvas rendered as lines of text, e.g. for terminals.
+pub struct TextCanvas {
+    mode: CanvasMode,
+    columns: usize,
+    rows: usize,
+    /// The dots of each character, as a bit field.
+    cells: Vec<u8>,
+}
+
+impl TextCanvas {
+    /// Create a new, empty canvas of the given size in characters, of at least
+    /// one column and one row.
+    pub fn new(columns: usize, rows: usize, mode: CanvasMode) -> TextCanvas {
+        let (columns, rows) = (columns.max(1), rows.max(1));
+        TextCanvas {
+            mode: mode,
+            columns: columns,
+            rows: rows,
+            cells: vec![0; columns * rows],
+        }
+    }
+
+    /// Get the canvas width, in dots.
+    pub fn width(&self) -> usize {
+        self.columns * self.mode.resolution().0
+    }
+
+    /// Get the canvas height, in dots.
+    pub fn height(&self) -> usize {
+        self.rows * self.mode.resolution().1
+    }
+
+    pub fn clear(&mut self) {
+        for cell in &mut self.cells {
+            *cell = 0;
+        }
+    }
+
+    /// Set the dot at the given position, if inside the canvas.
+    pub fn set(&mut self, x: usize, y: usize) {
+        if x >= self.width() || y >= self.height() {
+            return;
+        }
+        let (res_x, res_y) = self.mode.resolution();
+        let (dot_x, dot_y) = (x % res_x, y % res_y);
+        let bit = match self.mode {
+            CanvasMode::Braille => {
+                match (dot_x, dot_y) {
+                    (0, 3) => 6,
+                    (1, 3) => 7,
+                    (0, _) => dot_y,
+                    _ => 3 + dot_y,
+                }
+            }
+            CanvasMode::Ascii => dot_y,
+        };
+        self.cells[(y / res_y) * self.columns + x / res_x] |= 1 << bit;
+    }
+
+    /// Draw the given segments, projected with the given viewport.
+    pub fn draw_segments(&mut self, segments: &[Segment], viewport: &Viewport) {
+        let (width, height) = (self.width(), self.height());
+        for segment in segments {
+            for_each_pixel(viewport.project(&segment.from),
+                           viewport.project(&segment.to),
+                           width,
+                           height,
+                           |x, y| self.set(x, y));
+        }
+    }
+
+    /// Get the canvas content, as one string per row.
+    pub fn lines(&self) -> Vec<String> {
+        self.cells
+            .chunks(self.columns)
+            .map(|row| {
+                row.iter()
+                    .map(|&cell| match self.mode {
+                        CanvasMode::Braille => char::from_u32(0x2800 + cell as u32).unwrap(),
+                        CanvasMode::Ascii => [' ', '\'', '.', ':'][cell as usize],
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use turtle::{Segment, Vector3};
+    use render::{Bounds, Viewport};
+    use super::*;
+
+    #[test]
+    fn braille_canvas() {
+        let mut canvas = TextCanvas::new(2, 1, CanvasMode::Braille);
+        assert_eq!((canvas.width(), canvas.height()), (4, 4));
+        canvas.set(0, 0);
+        canvas.set(1, 3);
+        canvas.set(2, 2);
+        canvas.set(10, 10);
+        assert_eq!(canvas.lines(), vec!["\u{2881}\u{2804}".to_string()]);
+
+        canvas.clear();
+        let segments = [Segment {
+                            from: Vector3::new(0.0, 0.0, 0.0),
+                            to: Vector3::new(0.0, 3.0, 0.0),
+                        }];
+        let viewport = Viewport::fit(&Bounds::of_segments(&segments).unwrap(), 4.0, 4.0, 0.5);
+        canvas.draw_segments(&segments, &viewport);
+        assert_eq!(canvas.lines(), vec!["\u{2800}\u{2847}".to_string()]);
+    }
+
+    #[test]
+    fn ascii_canvas() {
+        let mut canvas = TextCanvas::new(3, 2, CanvasMode::Ascii);
+        canvas.set(0, 0);
+        canvas.set(1, 1);
+        canvas.set(2, 2);
+        canvas.set(2, 3);
+        assert_eq!(canvas.lines(), vec!["'. ".to_string(), "  :".to_string()]);
+
+        // e.g. for terminals reporting no size
+        let canvas = TextCanvas::new(0, 0, CanvasMode::Ascii);
+        assert_eq!((canvas.width(), canvas.height()), (1, 2));
+        assert_eq!(canvas.lines(), vec![" ".to_string()]);
+    }
+}
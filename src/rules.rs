@@ -1,43 +1,87 @@
 use std::hash::Hash;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use super::interpret::TurtleCommand;
+use super::turtle::Jitter;
+
+/// Maximum recursion depth when applying the decomposition rules.
+pub const MAX_DECOMPOSITION_DEPTH: usize = 64;
 
 /// These rules describe how an L-System should evolve to its next
 /// state and how this state should be interpreted in order to draw it.
-/// These two different concepts are stored alongside to facilitate the definition
-/// of L-System, both in code and in file.
+/// These two different concepts are independent : a symbol can have a
+/// production, an interpretation, both or none of them.
 ///
 /// A production (or evolution) rule consists in a symbol combined with its
-/// corresponding result. The internal data structure is up to the structures
+/// corresponding result, the symbols without production being kept as is
+/// (identity production). The internal data structure is up to the structures
 /// implementing this trait.
 ///
 /// See 'TurtleCommand' for further detail on the interpretation rules.
 pub trait LRules<S: Eq> {
     /// Get the production of the given symbol, or None if no matching rule is
-    /// found.
+    /// found (the symbol then producing itself).
     fn production(&self, symbol: &S) -> Option<&Vec<S>>;
 
     /// Get the interpreter command for the given symbol, or None if no matching
     /// command is found.
     fn interpretation(&self, symbol: &S) -> Option<&TurtleCommand>;
 
-    /// Get the expansion size of the worse-case production.
+    /// Get the decomposition of the given symbol, or None if no matching rule
+    /// is found.
+    /// Decomposition rules are recursively applied to the state after each
+    /// production step.
+    fn decomposition(&self, _symbol: &S) -> Option<&Vec<S>> {
+        None
+    }
+
+    /// Return false only if no symbol has a decomposition, allowing the
+    /// processors to skip the decomposition step.
+    /// NB : this defaults to true, so implementors must override it to get
+    /// this fast path.
+    fn has_decompositions(&self) -> bool {
+        true
+    }
+
+    /// Get the homomorphism of the given symbol, or None if no matching rule
+    /// is found.
+    /// Homomorphism rules are recursively applied before interpretation only,
+    /// leaving the state itself untouched.
+    fn homomorphism(&self, _symbol: &S) -> Option<&Vec<S>> {
+        None
+    }
+
+    /// Get the tropism susceptibility overriding the turtle's default one when
+    /// the given symbol is executed, or None if no override is found.
+    fn tropism(&self, _symbol: &S) -> Option<f32> {
+        None
+    }
+
+    /// Get the jitter overriding the turtle's default one when the given
+    /// symbol is executed, or None if no override is found.
+    fn jitter(&self, _symbol: &S) -> Option<&Jitter> {
+        None
+    }
+
+    /// Get the expansion size of the worse-case production, including the
+    /// decompositions applied to it.
     fn biggest_expansion(&self) -> usize;
 
     /// Get the average expansion size of the production rules.
     fn average_expansion(&self) -> f64;
 }
 
-#[derive(Clone, Debug)]
-struct SymbolRule<S: Eq>(Vec<S>, TurtleCommand);
-
 /// HashMap-based Rules structure.
 #[derive(Clone, Debug)]
 pub struct HashMapRules<S>
     where S: Eq + Hash
 {
-    rules: HashMap<S, SymbolRule<S>>,
+    productions: HashMap<S, Vec<S>>,
+    interpretations: HashMap<S, TurtleCommand>,
+    decompositions: HashMap<S, Vec<S>>,
+    homomorphisms: HashMap<S, Vec<S>>,
+    tropisms: HashMap<S, f32>,
+    jitters: HashMap<S, Jitter>,
     biggest_expansion: usize,
     average_expansion: f64,
 }
@@ -47,33 +91,160 @@ impl<S> HashMapRules<S>
 {
     pub fn new() -> HashMapRules<S> {
         HashMapRules {
-            rules: HashMap::new(),
+            productions: HashMap::new(),
+            interpretations: HashMap::new(),
+            decompositions: HashMap::new(),
+            homomorphisms: HashMap::new(),
+            tropisms: HashMap::new(),
+            jitters: HashMap::new(),
             biggest_expansion: 0,
             average_expansion: 0f64,
         }
     }
 
-    /// Add a new symbol rule or modify an existing one.
+    /// Set both the production and the interpretation of a symbol.
+    /// Return true if an existing rule was modified, false otherwise.
+    pub fn set(&mut self, symbol: S, production: Vec<S>, interpretation: TurtleCommand) -> bool
+        where S: Clone
+    {
+        let modified = self.interpretations.insert(symbol.clone(), interpretation).is_some();
+        self.set_production(symbol, production) || modified
+    }
+
+    /// Add a new production or modify an existing one, leaving the symbol
+    /// interpretation untouched.
+    /// Return true if an existing rule was modified, false otherwise.
+    pub fn set_production(&mut self, symbol: S, production: Vec<S>) -> bool {
+        let modified = self.productions.insert(symbol, production).is_some();
+        self.update_expansions();
+
+        modified
+    }
+
+    /// Add a new interpretation or modify an existing one, leaving the symbol
+    /// production untouched (the symbol producing itself if it has none).
     /// Return true if an existing rule was modified, false otherwise.
-    pub fn set(&mut self, symbol: S, production: Vec<S>, interpretation: TurtleCommand) -> bool {
-        let production_len = production.len();
-        let modified = match self.rules
-                  .insert(symbol, SymbolRule(production, interpretation)) {
-            Some(_) => true,
-            None => false,
+    pub fn set_interpretation(&mut self, symbol: S, interpretation: TurtleCommand) -> bool {
+        self.interpretations.insert(symbol, interpretation).is_some()
+    }
+
+    /// Remove all the rules of the given symbol.
+    /// Return true if any rule was removed, false otherwise.
+    pub fn remove(&mut self, symbol: &S) -> bool {
+        let removed = [self.productions.remove(symbol).is_some(),
+                       self.interpretations.remove(symbol).is_some(),
+                       self.decompositions.remove(symbol).is_some(),
+                       self.homomorphisms.remove(symbol).is_some(),
+                       self.tropisms.remove(symbol).is_some(),
+                       self.jitters.remove(symbol).is_some()];
+        self.update_expansions();
+
+        removed.iter().any(|&r| r)
+    }
+
+    /// Get all the symbols having at least one rule, in no particular order.
+    pub fn symbols(&self) -> Vec<&S> {
+        let mut symbols: HashSet<&S> = self.productions.keys().collect();
+        symbols.extend(self.interpretations.keys());
+        symbols.extend(self.decompositions.keys());
+        symbols.extend(self.homomorphisms.keys());
+        symbols.extend(self.tropisms.keys());
+        symbols.extend(self.jitters.keys());
+        symbols.into_iter().collect()
+    }
+
+    /// Compute the biggest and average expansions of the production rules, the
+    /// biggest one including the decompositions.
+    fn update_expansions(&mut self) {
+        self.biggest_expansion = self.biggest_decomposed_expansion();
+        let lengths = self.productions.values().map(|p| p.len());
+        self.average_expansion = match self.productions.len() {
+            0 => 0f64,
+            n => lengths.sum::<usize>() as f64 / n as f64,
         };
+    }
 
-        // biggest expansion computing
-        if production_len > self.biggest_expansion {
-            self.biggest_expansion = production_len;
+    /// Get the biggest length of a production once decomposed, the symbols
+    /// without production but with a decomposition producing themselves.
+    /// The decompositions exceeding 'MAX_DECOMPOSITION_DEPTH' are ignored, as
+    /// they make the iterations fail anyway.
+    fn biggest_decomposed_expansion(&self) -> usize {
+        let mut lengths = HashMap::new();
+        let mut biggest = 0;
+        for production in self.productions.values() {
+            let length = self.decomposed_length(production, 0, &mut lengths);
+            biggest = biggest.max(length.unwrap_or(production.len()));
+        }
+        for (symbol, decomposition) in &self.decompositions {
+            if !self.productions.contains_key(symbol) {
+                let length = self.decomposed_length(decomposition, 1, &mut lengths);
+                biggest = biggest.max(length.unwrap_or(1));
+            }
         }
-        // average expansion computing
-        assert!(self.rules.len() > 0);
-        self.average_expansion += (production_len as f64 - self.average_expansion) /
-                                  (self.rules.len() as f64);
+        biggest
+    }
+
+    /// Get the length of the given symbols once decomposed from the given
+    /// depth, or None if the maximum depth is exceeded, the decomposed length
+    /// of each symbol at each depth being kept in 'lengths'.
+    fn decomposed_length<'a>(&'a self,
+                             symbols: &'a [S],
+                             depth: usize,
+                             lengths: &mut HashMap<(&'a S, usize), Option<usize>>)
+                             -> Option<usize> {
+        let mut total = 0usize;
+        for symbol in symbols {
+            let length = match self.decompositions.get(symbol) {
+                Some(_) if depth >= MAX_DECOMPOSITION_DEPTH => None,
+                Some(decomposition) => {
+                    match lengths.get(&(symbol, depth)) {
+                        Some(&length) => length,
+                        None => {
+                            let length = self.decomposed_length(decomposition,
+                                                                depth + 1,
+                                                                lengths);
+                            lengths.insert((symbol, depth), length);
+                            length
+                        }
+                    }
+                }
+                None => Some(1),
+            };
+            match length {
+                Some(length) => total = total.saturating_add(length),
+                None => return None,
+            }
+        }
+        Some(total)
+    }
+
+    /// Add a new decomposition rule or modify an existing one.
+    /// Return true if an existing rule was modified, false otherwise.
+    pub fn set_decomposition(&mut self, symbol: S, decomposition: Vec<S>) -> bool {
+        let modified = self.decompositions.insert(symbol, decomposition).is_some();
+        self.update_expansions();
 
         modified
     }
+
+    /// Add a new homomorphism rule or modify an existing one.
+    /// Return true if an existing rule was modified, false otherwise.
+    pub fn set_homomorphism(&mut self, symbol: S, homomorphism: Vec<S>) -> bool {
+        self.homomorphisms.insert(symbol, homomorphism).is_some()
+    }
+
+    /// Set the tropism susceptibility to use when the given symbol is executed
+    /// by the turtle.
+    /// Return true if an existing override was modified, false otherwise.
+    pub fn set_tropism(&mut self, symbol: S, susceptibility: f32) -> bool {
+        self.tropisms.insert(symbol, susceptibility).is_some()
+    }
+
+    /// Set the jitter to use when the given symbol is executed by the turtle.
+    /// Return true if an existing override was modified, false otherwise.
+    pub fn set_jitter(&mut self, symbol: S, jitter: Jitter) -> bool {
+        self.jitters.insert(symbol, jitter).is_some()
+    }
 }
 
 impl HashMapRules<char> {
@@ -87,17 +258,49 @@ impl HashMapRules<char> {
                    -> bool {
         self.set(symbol, production.chars().collect(), interpretation)
     }
+
+    /// Convenience method for calling 'set_decomposition' directly with an str
+    /// slice.
+    pub fn set_decomposition_str(&mut self, symbol: char, decomposition: &str) -> bool {
+        self.set_decomposition(symbol, decomposition.chars().collect())
+    }
+
+    /// Convenience method for calling 'set_homomorphism' directly with an str
+    /// slice.
+    pub fn set_homomorphism_str(&mut self, symbol: char, homomorphism: &str) -> bool {
+        self.set_homomorphism(symbol, homomorphism.chars().collect())
+    }
 }
 
 impl<S> LRules<S> for HashMapRules<S>
     where S: Eq + Hash
 {
     fn production(&self, symbol: &S) -> Option<&Vec<S>> {
-        self.rules.get(symbol).map(|r| &r.0)
+        self.productions.get(symbol)
     }
 
     fn interpretation(&self, symbol: &S) -> Option<&TurtleCommand> {
-        self.rules.get(symbol).map(|r| &r.1)
+        self.interpretations.get(symbol)
+    }
+
+    fn decomposition(&self, symbol: &S) -> Option<&Vec<S>> {
+        self.decompositions.get(symbol)
+    }
+
+    fn has_decompositions(&self) -> bool {
+        !self.decompositions.is_empty()
+    }
+
+    fn homomorphism(&self, symbol: &S) -> Option<&Vec<S>> {
+        self.homomorphisms.get(symbol)
+    }
+
+    fn tropism(&self, symbol: &S) -> Option<f32> {
+        self.tropisms.get(symbol).cloned()
+    }
+
+    fn jitter(&self, symbol: &S) -> Option<&Jitter> {
+        self.jitters.get(symbol)
     }
 
     fn biggest_expansion(&self) -> usize {
@@ -156,4 +359,60 @@ mod test {
         assert_eq!(rules.production(&b'B'), Some(&b"A".to_vec()));
         assert_eq!(rules.production(&b'C'), None);
     }
+
+    #[test]
+    fn hashmap_rules_decomposition_homomorphism() {
+        let mut rules: HashMapRules<char> = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::None);
+        assert!(!rules.set_decomposition_str('B', "CD"));
+        assert!(rules.set_decomposition_str('B', "C"));
+        assert!(!rules.set_homomorphism_str('A', "F+F"));
+
+        assert_eq!(rules.decomposition(&'B'), Some(&vec!['C']));
+        assert_eq!(rules.decomposition(&'A'), None);
+        assert_eq!(rules.homomorphism(&'A'), Some(&"F+F".chars().collect()));
+        assert_eq!(rules.homomorphism(&'B'), None);
+        assert_eq!(rules.biggest_expansion(), 2);
+
+        // the decompositions are applied after the productions
+        rules.set_decomposition_str('C', "DDD");
+        assert_eq!(rules.biggest_expansion(), 4);
+        rules.set_decomposition_str('E', "DDDDDD");
+        assert_eq!(rules.biggest_expansion(), 6);
+        assert_eq!(rules.average_expansion(), 2.0);
+        // endless decompositions are ignored
+        rules.set_decomposition_str('D', "DD");
+        assert_eq!(rules.biggest_expansion(), 2);
+    }
+
+    #[test]
+    fn hashmap_rules_edition() {
+        let mut rules: HashMapRules<char> = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::AdvanceBy(1f32));
+        rules.set_str('B', "A", TurtleCommand::None);
+        assert!(rules.set_production('A', "ABBB".chars().collect()));
+        assert_eq!(rules.interpretation(&'A'), Some(&TurtleCommand::AdvanceBy(1f32)));
+        assert_eq!(rules.biggest_expansion(), 4);
+        assert_eq!(rules.average_expansion(), 2.5);
+
+        // interpretation only, the symbol producing itself
+        assert!(!rules.set_interpretation('+', TurtleCommand::RotateBy(90f32)));
+        assert_eq!(rules.production(&'+'), None);
+        assert_eq!(rules.interpretation(&'+'), Some(&TurtleCommand::RotateBy(90f32)));
+        assert!(rules.set_interpretation('B', TurtleCommand::RotateBy(90f32)));
+        assert_eq!(rules.production(&'B'), Some(&vec!['A']));
+        assert_eq!(rules.average_expansion(), 2.5);
+        assert!(!rules.set_production('-', vec![]));
+        assert_eq!(rules.interpretation(&'-'), None);
+
+        rules.set_homomorphism_str('C', "AA");
+        let mut symbols = rules.symbols();
+        symbols.sort();
+        assert_eq!(symbols, vec![&'+', &'-', &'A', &'B', &'C']);
+
+        assert!(rules.remove(&'A'));
+        assert!(!rules.remove(&'A'));
+        assert_eq!(rules.production(&'A'), None);
+        assert_eq!(rules.biggest_expansion(), 1);
+    }
 }
@@ -8,6 +8,96 @@ pub fn new_rules_value<'a, S: Eq, R: 'a + LRules<S> + Sync + Send>(rules: R) ->
     Arc::new(Box::new(rules))
 }
 
+/// Describes which production table of a table L-System is active at each
+/// iteration.
+#[derive(Clone)]
+pub enum TableSchedule {
+    /// Use the tables in the given order, one per iteration, then keep using
+    /// the last one.
+    Sequence(Vec<usize>),
+    /// Cycle through the tables in the given order, one per iteration.
+    Cyclic(Vec<usize>),
+    /// Let the given function select the table from the iteration number.
+    Callback(Arc<Fn(u64) -> usize + Send + Sync>),
+}
+
+impl TableSchedule {
+    /// Get the index of the table to use when iterating from the given
+    /// iteration.
+    pub fn table_index(&self, iteration: u64) -> usize {
+        use self::TableSchedule::*;
+        match *self {
+            Sequence(ref order) => {
+                let last = order.len() as u64 - 1;
+                order[if iteration > last { last } else { iteration } as usize]
+            }
+            Cyclic(ref order) => order[(iteration % order.len() as u64) as usize],
+            Callback(ref callback) => callback(iteration),
+        }
+    }
+}
+
+/// Several sets of production rules, selected per iteration according to a
+/// 'TableSchedule' (see table L-Systems, e.g. for seasonal growth models).
+#[derive(Clone)]
+pub struct RulesTables<'a, S>
+    where S: Eq
+{
+    tables: Vec<RulesValue<'a, S>>,
+    schedule: TableSchedule,
+}
+
+impl<'a, S> RulesTables<'a, S>
+    where S: Eq
+{
+    /// Try and create a new set of production tables with the given schedule.
+    pub fn new(tables: Vec<RulesValue<'a, S>>,
+               schedule: TableSchedule)
+               -> Result<RulesTables<'a, S>, String> {
+        if tables.is_empty() {
+            return Err(format!("RulesTables::new : no production table given"));
+        }
+        match schedule {
+            TableSchedule::Sequence(ref order) |
+            TableSchedule::Cyclic(ref order) => {
+                if order.is_empty() {
+                    return Err(format!("RulesTables::new : empty table schedule"));
+                }
+                if let Some(i) = order.iter().find(|&&i| i >= tables.len()) {
+                    return Err(format!("RulesTables::new : invalid table index ({}) in \
+                                        schedule",
+                                       i));
+                }
+            }
+            TableSchedule::Callback(_) => (),
+        }
+        Ok(RulesTables {
+               tables: tables,
+               schedule: schedule,
+           })
+    }
+
+    /// Get the production tables.
+    pub fn tables(&self) -> &[RulesValue<'a, S>] {
+        &self.tables[..]
+    }
+
+    /// Get the table schedule.
+    pub fn schedule(&self) -> &TableSchedule {
+        &self.schedule
+    }
+
+    /// Get the production table to use when iterating from the given
+    /// iteration.
+    pub fn rules(&self, iteration: u64) -> Result<&RulesValue<'a, S>, String> {
+        let index = self.schedule.table_index(iteration);
+        self.tables.get(index).ok_or(format!("RulesTables : invalid table index ({}) for \
+                                              iteration {}",
+                                             index,
+                                             iteration))
+    }
+}
+
 
 /// Structure containing all that is needed to fully describe the current state
 /// of an L-System.
@@ -20,8 +110,11 @@ pub struct LSystem<'a, S>
     iteration: u64,
     /// The current internal state of the L-System, stored as a list of symbols.
     state: Vec<S>,
-    /// The L-System's production rules.
+    /// The L-System's production rules (for table L-Systems, the table which
+    /// produced the current state, or the first scheduled one for the axiom).
     rules: RulesValue<'a, S>,
+    /// The production tables, for table L-Systems only.
+    tables: Option<Arc<RulesTables<'a, S>>>,
 }
 
 impl<'a, S> LSystem<'a, S>
@@ -30,14 +123,54 @@ impl<'a, S> LSystem<'a, S>
     /// Create a new L-System with the given axiom (initial state, or seed)
     /// and production rules.
     /// Optionally, one can specify the current iteration of the L-System.
-    pub fn new(axiom: Vec<S>, rules: RulesValue<'a, S>, iteration: Option<u64>) -> LSystem<S> {
+    pub fn new(axiom: Vec<S>, rules: RulesValue<'a, S>, iteration: Option<u64>) -> LSystem<'a, S> {
         LSystem {
             rules: rules,
+            tables: None,
             iteration: iteration.unwrap_or(0),
             state: axiom,
         }
     }
 
+    /// Try and create a new table L-System with the given axiom and production
+    /// tables, the schedule having to be valid for the first iteration.
+    /// Optionally, one can specify the current iteration of the L-System.
+    pub fn new_with_tables(axiom: Vec<S>,
+                           tables: RulesTables<'a, S>,
+                           iteration: Option<u64>)
+                           -> Result<LSystem<'a, S>, String> {
+        let iteration = iteration.unwrap_or(0);
+        let rules = try!(tables.rules(iteration)).clone();
+        Ok(LSystem {
+               rules: rules,
+               tables: Some(Arc::new(tables)),
+               iteration: iteration,
+               state: axiom,
+           })
+    }
+
+    /// Create the next generation of the L-System from its evolved state,
+    /// produced with the rules given by 'next_rules'.
+    pub fn evolve(&self, state: Vec<S>) -> Result<LSystem<'a, S>, String> {
+        Ok(LSystem {
+               rules: try!(self.next_rules()).clone(),
+               tables: self.tables.clone(),
+               iteration: self.iteration + 1,
+               state: state,
+           })
+    }
+
+    /// Create an L-System of the same generation and rules with the given
+    /// state, e.g. a part of the current one.
+    pub fn with_state(&self, state: Vec<S>) -> LSystem<'a, S> {
+        LSystem {
+            rules: self.rules.clone(),
+            tables: self.tables.clone(),
+            iteration: self.iteration,
+            state: state,
+        }
+    }
+
     /// Get the current iteration/generation.
     pub fn iteration(&self) -> u64 {
         self.iteration
@@ -48,10 +181,26 @@ impl<'a, S> LSystem<'a, S>
         &self.state[..]
     }
 
-    /// Get the production rules.
+    /// Get the production rules of the current generation : for table
+    /// L-Systems, the table which produced it (see 'next_rules' for the one
+    /// producing the next generation).
     pub fn rules(&self) -> &RulesValue<'a, S> {
         &self.rules
     }
+
+    /// Try and get the production rules to use for the next iteration, the
+    /// production table of table L-Systems being selected only then.
+    pub fn next_rules(&self) -> Result<&RulesValue<'a, S>, String> {
+        match self.tables {
+            Some(ref tables) => tables.rules(self.iteration),
+            None => Ok(&self.rules),
+        }
+    }
+
+    /// Get the production tables, if this is a table L-System.
+    pub fn tables(&self) -> Option<&RulesTables<'a, S>> {
+        self.tables.as_ref().map(|t| &**t)
+    }
 }
 
 pub type AsciiLSystem<'a> = LSystem<'a, u8>;
@@ -62,3 +211,57 @@ impl<'a> LSystem<'a, char> {
         LSystem::new(axiom.chars().collect(), rules, None)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use rules::HashMapRules;
+    use process::{LProcessor, SimpleProcessor};
+    use interpret::TurtleCommand;
+    use super::*;
+
+    fn tables() -> Vec<RulesValue<'static, char>> {
+        let mut summer = HashMapRules::new();
+        summer.set_str('A', "AB", TurtleCommand::None);
+        let mut winter = HashMapRules::new();
+        winter.set_str('A', "AC", TurtleCommand::None);
+        vec![new_rules_value(summer), new_rules_value(winter)]
+    }
+
+    fn derive(schedule: TableSchedule, iterations: usize) -> String {
+        let tables = RulesTables::new(tables(), schedule).ok().unwrap();
+        let mut lsystem = LSystem::new_with_tables(vec!['A'], tables, None).ok().unwrap();
+        for _ in 0..iterations {
+            lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        }
+        assert_eq!(lsystem.iteration(), iterations as u64);
+        lsystem.state().iter().cloned().collect()
+    }
+
+    #[test]
+    fn table_schedules() {
+        assert_eq!(derive(TableSchedule::Sequence(vec![1, 0]), 4), "ABBBC");
+        assert_eq!(derive(TableSchedule::Cyclic(vec![0, 1]), 4), "ACBCB");
+        assert_eq!(derive(TableSchedule::Callback(Arc::new(|n| (n / 2 % 2) as usize)), 4),
+                   "ACCBB");
+    }
+
+    #[test]
+    fn table_schedules_validation() {
+        assert!(RulesTables::new(tables(), TableSchedule::Cyclic(vec![])).is_err());
+        assert!(RulesTables::new(tables(), TableSchedule::Sequence(vec![0, 2])).is_err());
+        assert!(RulesTables::<char>::new(vec![], TableSchedule::Cyclic(vec![0])).is_err());
+
+        // the table is selected when the iteration starts
+        let schedule = TableSchedule::Callback(Arc::new(|n| n as usize));
+        let tables = RulesTables::new(tables(), schedule).ok().unwrap();
+        let lsystem = LSystem::new_with_tables(vec!['A'], tables, None).ok().unwrap();
+        let lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        assert_eq!(lsystem.iteration(), 1);
+        assert!(lsystem.next_rules().is_ok());
+        let lsystem = SimpleProcessor.iterate(&lsystem).ok().unwrap();
+        assert_eq!(lsystem.state(), &['A', 'C', 'B']);
+        assert!(lsystem.next_rules().is_err());
+        assert!(SimpleProcessor.iterate(&lsystem).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+//! Out-of-core storage of L-System states too big to fit in memory.
+//! A 'DiskState' is a sequence of chunk files, written and read sequentially
+//! one chunk at a time, so that only a few chunks are in memory at once.
+//! A 'DiskLSystem' is the on-disk counterpart of 'LSystem', iterated with
+//! 'ChunksProcessor::iterate_on_disk' and interpreted one chunk at a time.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use interpret::{LInterpreter, TurtleCommand};
+use state::{LSystem, RulesTables, RulesValue};
+
+/// Symbols which can be stored on disk, with a fixed size encoding.
+pub trait DiskSymbol: Sized {
+    /// The encoded size, in bytes.
+    const SIZE: usize;
+    fn encode(&self, bytes: &mut [u8]);
+    fn decode(bytes: &[u8]) -> Result<Self, String>;
+}
+
+impl DiskSymbol for u8 {
+    const SIZE: usize = 1;
+
+    fn encode(&self, bytes: &mut [u8]) {
+        bytes[0] = *self;
+    }
+
+    fn decode(bytes: &[u8]) -> Result<u8, String> {
+        Ok(bytes[0])
+    }
+}
+
+impl DiskSymbol for char {
+    const SIZE: usize = 4;
+
+    fn encode(&self, bytes: &mut [u8]) {
+        let value = *self as u32;
+        for (i, byte) in bytes.iter_mut().take(4).enumerate() {
+            *byte = (value >> (8 * i)) as u8;
+        }
+    }
+
+    fn decode(bytes: &[u8]) -> Result<char, String> {
+        let value = bytes.iter()
+            .take(4)
+            .enumerate()
+            .fold(0u32, |value, (i, &byte)| value | (byte as u32) << (8 * i));
+        ::std::char::from_u32(value).ok_or(format!("invalid stored character ({:#x})", value))
+    }
+}
+
+/// Counter making the chunk file names unique within the process.
+static NEXT_STATE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// L-System state stored on disk as a sequence of chunk files, deleted when
+/// the 'DiskState' is dropped.
+pub struct DiskState<S: DiskSymbol> {
+    /// The directory of the chunk files.
+    directory: PathBuf,
+    /// The chunk files and their length in symbols.
+    chunks: Vec<(PathBuf, usize)>,
+    len: usize,
+    symbols: PhantomData<S>,
+}
+
+impl<S: DiskSymbol> DiskState<S> {
+    /// Get the total number of symbols.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the directory storing the chunk files.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Get the number of chunks.
+    pub fn chunks_number(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Read the chunk of the given index into memory.
+    pub fn read_chunk(&self, index: usize) -> Result<Vec<S>, String> {
+        let (ref path, len) = *try!(self.chunks
+            .get(index)
+            .ok_or(format!("DiskState::read_chunk : invalid chunk index ({})", index)));
+        let file = try!(File::open(path)
+            .map_err(|why| format!("cannot open {} : {}", path.display(), why)));
+        let mut bytes = Vec::with_capacity(len * S::SIZE);
+        try!(BufReader::new(file)
+            .read_to_end(&mut bytes)
+            .map_err(|why| format!("cannot read {} : {}", path.display(), why)));
+        if bytes.len() != len * S::SIZE {
+            return Err(format!("{} is truncated", path.display()));
+        }
+        bytes.chunks(S::SIZE).map(S::decode).collect()
+    }
+
+    /// Read the whole state into memory.
+    pub fn load(&self) -> Result<Vec<S>, String> {
+        let mut state = Vec::with_capacity(self.len);
+        for index in 0..self.chunks.len() {
+            state.extend(try!(self.read_chunk(index)));
+        }
+        Ok(state)
+    }
+}
+
+impl<S: DiskSymbol> Drop for DiskState<S> {
+    fn drop(&mut self) {
+        for path in self.chunks.iter().map(|c| &c.0) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Sequential writer of a 'DiskState', one chunk at a time.
+pub struct DiskStateWriter<S: DiskSymbol> {
+    /// The unique prefix of the chunk file names.
+    prefix: String,
+    state: DiskState<S>,
+}
+
+impl<S: DiskSymbol> DiskStateWriter<S> {
+    /// Create a new writer storing its chunks in the system's temporary
+    /// directory.
+    pub fn new() -> DiskStateWriter<S> {
+        DiskStateWriter::new_in(&env::temp_dir())
+    }
+
+    /// Create a new writer storing its chunks in the given directory.
+    pub fn new_in(directory: &Path) -> DiskStateWriter<S> {
+        DiskStateWriter {
+            prefix: format!("rustlsystem-{}-{}",
+                            process::id(),
+                            NEXT_STATE_ID.fetch_add(1, Ordering::SeqCst)),
+            state: DiskState {
+                directory: directory.to_path_buf(),
+                chunks: Vec::new(),
+                len: 0,
+                symbols: PhantomData,
+            },
+        }
+    }
+
+    /// Append the given symbols to the state, as a new chunk.
+    pub fn write_chunk(&mut self, symbols: &[S]) -> Result<(), String> {
+        let path = self.state
+            .directory
+            .join(format!("{}-{}.bin", self.prefix, self.state.chunks.len()));
+        let file = try!(File::create(&path)
+            .map_err(|why| format!("cannot create {} : {}", path.display(), why)));
+        // registered first, so that the file is removed even if writing fails
+        self.state.chunks.push((path, 0));
+
+        let mut writer = BufWriter::new(file);
+        let mut bytes = vec![0; S::SIZE];
+        for symbol in symbols {
+            symbol.encode(&mut bytes);
+            try!(writer.write_all(&bytes).map_err(|why| format!("cannot write chunk : {}", why)));
+        }
+        try!(writer.flush().map_err(|why| format!("cannot write chunk : {}", why)));
+
+        self.state.chunks.last_mut().unwrap().1 = symbols.len();
+        self.state.len = try!(self.state
+            .len
+            .checked_add(symbols.len())
+            .ok_or(format!("DiskStateWriter::write_chunk : usize overflow")));
+        Ok(())
+    }
+
+    /// Finish writing and return the state.
+    pub fn finish(self) -> DiskState<S> {
+        self.state
+    }
+}
+
+impl<S: DiskSymbol> Default for DiskStateWriter<S> {
+    fn default() -> DiskStateWriter<S> {
+        DiskStateWriter::new()
+    }
+}
+
+/// Store the given symbols on disk, in chunks of the given size.
+pub fn store<S: DiskSymbol>(symbols: &[S], chunk_size: usize) -> Result<DiskState<S>, String> {
+    let mut writer = DiskStateWriter::new();
+    for chunk in symbols.chunks(chunk_size.max(1)) {
+        try!(writer.write_chunk(chunk));
+    }
+    Ok(writer.finish())
+}
+
+/// L-System whose state is stored on disk, keeping track of its generation
+/// and production tables like 'LSystem'.
+pub struct DiskLSystem<'a, S>
+    where S: Eq + DiskSymbol
+{
+    /// The generation and rules, its in-memory state being left empty.
+    lsystem: LSystem<'a, S>,
+    state: DiskState<S>,
+}
+
+impl<'a, S> DiskLSystem<'a, S>
+    where S: Clone + Eq + DiskSymbol
+{
+    /// Store the given L-System on disk, in chunks of the given size.
+    pub fn store(lsystem: &LSystem<'a, S>,
+                 chunk_size: usize)
+                 -> Result<DiskLSystem<'a, S>, String> {
+        Ok(DiskLSystem {
+            lsystem: lsystem.with_state(Vec::new()),
+            state: try!(store(lsystem.state(), chunk_size)),
+        })
+    }
+
+    /// Create the next generation of the L-System from its evolved state (see
+    /// 'LSystem::evolve').
+    pub fn evolve(&self, state: DiskState<S>) -> Result<DiskLSystem<'a, S>, String> {
+        Ok(DiskLSystem {
+            lsystem: try!(self.lsystem.evolve(Vec::new())),
+            state: state,
+        })
+    }
+
+    /// Get the current iteration/generation.
+    pub fn iteration(&self) -> u64 {
+        self.lsystem.iteration()
+    }
+
+    /// Get the current state.
+    pub fn state(&self) -> &DiskState<S> {
+        &self.state
+    }
+
+    /// Get the production rules of the current generation (see
+    /// 'LSystem::rules').
+    pub fn rules(&self) -> &RulesValue<'a, S> {
+        self.lsystem.rules()
+    }
+
+    /// Try and get the production rules to use for the next iteration.
+    pub fn next_rules(&self) -> Result<&RulesValue<'a, S>, String> {
+        self.lsystem.next_rules()
+    }
+
+    /// Get the production tables, if this is a table L-System.
+    pub fn tables(&self) -> Option<&RulesTables<'a, S>> {
+        self.lsystem.tables()
+    }
+
+    /// Read the chunk of the given index, as an L-System of the same generation
+    /// and rules.
+    pub fn read_chunk(&self, index: usize) -> Result<LSystem<'a, S>, String> {
+        Ok(self.lsystem.with_state(try!(self.state.read_chunk(index))))
+    }
+
+    /// Read the whole L-System into memory.
+    pub fn load(&self) -> Result<LSystem<'a, S>, String> {
+        Ok(self.lsystem.with_state(try!(self.state.load())))
+    }
+
+    /// Interpret the state one chunk at a time, by batches of at most the given
+    /// size (see 'LInterpreter::interpret_batches'), the batches not spanning
+    /// several chunks.
+    /// NB : the symbols have to be interpreted independently of their
+    /// neighbours, as with the interpreters of this crate.
+    pub fn interpret_batches(&self,
+                             interpreter: &mut LInterpreter<S>,
+                             batch_size: usize,
+                             f: &mut FnMut(Vec<TurtleCommand>) -> bool)
+                             -> Result<(), String> {
+        let mut stopped = false;
+        for index in 0..self.state.chunks_number() {
+            let chunk = try!(self.read_chunk(index));
+            try!(interpreter.interpret_batches(&chunk, batch_size, &mut |batch| {
+                stopped = !f(batch);
+                !stopped
+            }));
+            if stopped {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rules::HashMapRules;
+    use state::{LSystem, RulesTables, TableSchedule, new_rules_value};
+    use interpret::{LInterpreter, SimpleInterpreter, TurtleCommand};
+    use super::*;
+
+    #[test]
+    fn disk_state() {
+        let symbols: Vec<char> = "ABAAB\u{1F33F}AB".chars().collect();
+        let state = store(&symbols, 3).ok().unwrap();
+        assert_eq!(state.len(), 8);
+        assert_eq!(state.chunks_number(), 3);
+        assert_eq!(state.read_chunk(2).ok().unwrap(), vec!['A', 'B']);
+        assert_eq!(state.load().ok().unwrap(), symbols);
+        assert!(state.read_chunk(3).is_err());
+
+        let paths: Vec<PathBuf> = state.chunks.iter().map(|c| c.0.clone()).collect();
+        assert!(paths.iter().all(|p| p.exists()));
+        drop(state);
+        assert!(paths.iter().all(|p| !p.exists()));
+
+        assert!(char::decode(&[0, 0xd8, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn disk_lsystem() {
+        let mut summer = HashMapRules::new();
+        summer.set_str('A', "AB", TurtleCommand::AdvanceBy(1.0));
+        summer.set_str('B', "A", TurtleCommand::RotateBy(90.0));
+        let mut winter = summer.clone();
+        winter.set_str('B', "B", TurtleCommand::RotateBy(90.0));
+        let tables = RulesTables::new(vec![new_rules_value(summer), new_rules_value(winter)],
+                                      TableSchedule::Cyclic(vec![0, 0, 1]))
+            .ok()
+            .unwrap();
+        let lsystem = LSystem::new_with_tables(vec!['A'], tables, Some(2)).ok().unwrap();
+        let disk = DiskLSystem::store(&lsystem, 4).ok().unwrap();
+        assert_eq!(disk.iteration(), 2);
+        assert!(disk.tables().is_some());
+        assert_eq!(disk.load().ok().unwrap().state(), lsystem.state());
+
+        // interpreted one chunk at a time
+        let state: Vec<char> = "ABAABABAAB".chars().collect();
+        let disk = DiskLSystem::store(&lsystem.with_state(state.clone()), 4).ok().unwrap();
+        let commands = SimpleInterpreter.interpret(&lsystem.with_state(state)).ok().unwrap();
+        let mut batches = Vec::new();
+        disk.interpret_batches(&mut SimpleInterpreter, 3, &mut |batch| {
+                batches.push(batch);
+                true
+            })
+            .ok()
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.len()).collect::<Vec<_>>(), vec![3, 1, 3, 1, 2]);
+        assert_eq!(batches.concat(), commands);
+        let mut received = 0;
+        disk.interpret_batches(&mut SimpleInterpreter, 3, &mut |_| {
+                received += 1;
+                false
+            })
+            .ok()
+            .unwrap();
+        assert_eq!(received, 1);
+    }
+}
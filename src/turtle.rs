@@ -0,0 +1,478 @@
+use std::ops::{Add, Sub, Mul};
+
+use super::state::LSystem;
+use super::interpret::{TurtleCommand, for_each_interpretation, for_each_growth_interpretation};
+
+/// Simple 3D vector.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vector3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vector3 {
+    pub fn new(x: f32, y: f32, z: f32) -> Vector3 {
+        Vector3 { x: x, y: y, z: z }
+    }
+
+    pub fn dot(&self, other: &Vector3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(&self, other: &Vector3) -> Vector3 {
+        Vector3::new(self.y * other.z - self.z * other.y,
+                     self.z * other.x - self.x * other.z,
+                     self.x * other.y - self.y * other.x)
+    }
+
+    pub fn norm(&self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// Get the unit vector of the same direction, or the vector itself if null.
+    pub fn normalized(&self) -> Vector3 {
+        let norm = self.norm();
+        if norm > 0.0 { *self * (1.0 / norm) } else { *self }
+    }
+
+    /// Rotate the vector around the given unit axis by the given angle, in
+    /// radians (Rodrigues' rotation formula).
+    pub fn rotated(&self, axis: &Vector3, angle: f32) -> Vector3 {
+        let (sin, cos) = angle.sin_cos();
+        *self * cos + axis.cross(self) * sin + *axis * (axis.dot(self) * (1.0 - cos))
+    }
+}
+
+impl Add for Vector3 {
+    type Output = Vector3;
+    fn add(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vector3 {
+    type Output = Vector3;
+    fn sub(self, other: Vector3) -> Vector3 {
+        Vector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f32> for Vector3 {
+    type Output = Vector3;
+    fn mul(self, factor: f32) -> Vector3 {
+        Vector3::new(self.x * factor, self.y * factor, self.z * factor)
+    }
+}
+
+/// Segment drawn by the turtle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Segment {
+    pub from: Vector3,
+    pub to: Vector3,
+}
+
+/// Tropism bending the turtle heading toward a given direction after each
+/// advance, by an angle proportional to the susceptibility and to the norm of
+/// the cross product between the heading and the direction (see "The
+/// Algorithmic Beauty of Plants", section 2.4).
+/// A negative susceptibility bends the heading away from the direction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tropism {
+    /// The tropism direction (e.g. (0, -1, 0) for gravity).
+    pub direction: Vector3,
+    /// The default susceptibility, overridable per symbol with
+    /// 'LRules::tropism'.
+    pub susceptibility: f32,
+}
+
+/// Random variation of the turtle commands, making the drawings look less
+/// synthetic.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Jitter {
+    /// The maximum deviation of the rotation angles, in degrees.
+    pub angle: f32,
+    /// The maximum relative deviation of the advance lengths (e.g. 0.1 for
+    /// +/- 10%).
+    pub length: f32,
+}
+
+/// Configuration of the turtle executor.
+#[derive(Clone, Debug, Default)]
+pub struct TurtleConfig {
+    /// The optional tropism applied after each advance.
+    pub tropism: Option<Tropism>,
+    /// The optional default jitter, overridable per symbol with
+    /// 'LRules::jitter'.
+    pub jitter: Option<Jitter>,
+    /// The seed of the jitter random numbers: a given seed always produces the
+    /// same drawing.
+    pub seed: u64,
+}
+
+/// Small xorshift64* pseudo-random number generator, so that a given seed
+/// yields the same sequence on every platform.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        // the state must never be zero
+        match seed ^ 0x9E37_79B9_7F4A_7C15 {
+            0 => Random(0x9E37_79B9_7F4A_7C15),
+            state => Random(state),
+        }
+    }
+
+    /// Get the next random number, uniformly distributed in [-1, 1).
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let n = self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 40;
+        (n as f32 / (1u64 << 23) as f32) - 1.0
+    }
+}
+
+/// State of the turtle: its position and its orientation frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TurtleState {
+    pub position: Vector3,
+    /// The heading vector, i.e. the direction of advances.
+    pub heading: Vector3,
+    /// The left vector.
+    pub left: Vector3,
+    /// The up vector.
+    pub up: Vector3,
+}
+
+impl TurtleState {
+    /// The initial state: at the origin, heading toward +Y with +Z as up, so
+    /// that 2D L-Systems are drawn in the XY plane.
+    pub fn new() -> TurtleState {
+        TurtleState {
+            position: Vector3::new(0.0, 0.0, 0.0),
+            heading: Vector3::new(0.0, 1.0, 0.0),
+            left: Vector3::new(-1.0, 0.0, 0.0),
+            up: Vector3::new(0.0, 0.0, 1.0),
+        }
+    }
+}
+
+/// Turtle executor, turning interpreted turtle commands into 3D segments.
+pub struct Turtle {
+    config: TurtleConfig,
+    state: TurtleState,
+    stack: Vec<TurtleState>,
+    random: Random,
+}
+
+impl Turtle {
+    pub fn new(config: TurtleConfig) -> Turtle {
+        let random = Random::new(config.seed);
+        Turtle {
+            config: config,
+            state: TurtleState::new(),
+            stack: Vec::new(),
+            random: random,
+        }
+    }
+
+    /// Get the current turtle state.
+    pub fn state(&self) -> &TurtleState {
+        &self.state
+    }
+
+    /// Reset the turtle to its initial state, and its random numbers to its
+    /// seed.
+    pub fn reset(&mut self) {
+        self.state = TurtleState::new();
+        self.stack.clear();
+        self.random = Random::new(self.config.seed);
+    }
+
+    /// Apply the given command, with the default tropism susceptibility and
+    /// jitter, and return the drawn segment if any.
+    /// NB: popping an empty stack does nothing.
+    pub fn apply(&mut self, command: &TurtleCommand) -> Option<Segment> {
+        let susceptibility = self.config.tropism.as_ref().map_or(0.0, |t| t.susceptibility);
+        let jitter = self.config.jitter.clone();
+        self.apply_with(command, susceptibility, jitter.as_ref())
+    }
+
+    /// Apply the given command with the given tropism susceptibility and
+    /// jitter, and return the drawn segment if any.
+    pub fn apply_with(&mut self,
+                      command: &TurtleCommand,
+                      susceptibility: f32,
+                      jitter: Option<&Jitter>)
+                      -> Option<Segment> {
+        use interpret::TurtleCommand::*;
+        let command = match jitter {
+            Some(jitter) => self.jittered(command, jitter),
+            Option::None => command.clone(),
+        };
+        let state = &mut self.state;
+        match command {
+            AdvanceBy(length) => {
+                let from = state.position;
+                state.position = from + state.heading * length;
+                if let Some(ref tropism) = self.config.tropism {
+                    Turtle::bend(state, &tropism.direction, susceptibility);
+                }
+                return Some(Segment {
+                    from: from,
+                    to: state.position,
+                });
+            }
+            RotateBy(angle) => {
+                let (h, l) = Turtle::rotate(state.heading, state.left, angle);
+                state.heading = h;
+                state.left = l;
+            }
+            PitchBy(angle) => {
+                let (h, u) = Turtle::rotate(state.heading, state.up * -1.0, angle);
+                state.heading = h;
+                state.up = u * -1.0;
+            }
+            RollBy(angle) => {
+                let (l, u) = Turtle::rotate(state.left, state.up * -1.0, angle);
+                state.left = l;
+                state.up = u * -1.0;
+            }
+            PushState => self.stack.push(state.clone()),
+            PopState => {
+                if let Some(s) = self.stack.pop() {
+                    *state = s;
+                }
+            }
+            None => (),
+        }
+        Option::None
+    }
+
+    /// Execute the given commands from the initial state and return the drawn
+    /// segments.
+    pub fn execute(&mut self, commands: &[TurtleCommand]) -> Vec<Segment> {
+        self.reset();
+        commands.iter().filter_map(|c| self.apply(c)).collect()
+    }
+
+    /// Interpret the given L-System state and execute the resulting commands
+    /// from the initial state, taking into account the per-symbol tropism
+    /// susceptibilities of its rules.
+    /// Return an error if the maximum homomorphism depth is exceeded.
+    pub fn execute_lsystem<S: Eq>(&mut self, lsystem: &LSystem<S>) -> Result<Vec<Segment>, String> {
+        self.reset();
+        let rules = lsystem.rules();
+        let default = self.config.tropism.as_ref().map_or(0.0, |t| t.susceptibility);
+        let default_jitter = self.config.jitter.clone();
+        let mut segments = Vec::new();
+        try!(for_each_interpretation(lsystem.state(), &***rules, &mut |symbol, command| {
+            let susceptibility = rules.tropism(symbol).unwrap_or(default);
+            let jitter = rules.jitter(symbol).or(default_jitter.as_ref());
+            if let Some(segment) = self.apply_with(command, susceptibility, jitter) {
+                segments.push(segment);
+            }
+            true
+        }));
+        Ok(segments)
+    }
+
+    /// Execute the growth of the given L-System toward its next generation at
+    /// the given time 't' in [0, 1] (see
+    /// 'interpret::for_each_growth_interpretation'), taking into account the
+    /// per-symbol tropism susceptibilities and jitters of its rules.
+    pub fn execute_growth<S: Eq>(&mut self,
+                                 lsystem: &LSystem<S>,
+                                 t: f32)
+                                 -> Result<Vec<Segment>, String> {
+        self.reset();
+        // without a valid next table, the next iteration fails anyway
+        let rules = lsystem.next_rules().unwrap_or(lsystem.rules());
+        let default = self.config.tropism.as_ref().map_or(0.0, |t| t.susceptibility);
+        let default_jitter = self.config.jitter.clone();
+        let mut segments = Vec::new();
+        try!(for_each_growth_interpretation(lsystem.state(), &***rules, t, &mut |symbol, command| {
+            let susceptibility = rules.tropism(symbol).unwrap_or(default);
+            let jitter = rules.jitter(symbol).or(default_jitter.as_ref());
+            if let Some(segment) = self.apply_with(command, susceptibility, jitter) {
+                segments.push(segment);
+            }
+        }));
+        Ok(segments)
+    }
+
+    /// Randomly vary the given command according to the given jitter.
+    fn jittered(&mut self, command: &TurtleCommand, jitter: &Jitter) -> TurtleCommand {
+        use interpret::TurtleCommand::*;
+        match *command {
+            AdvanceBy(length) if jitter.length != 0.0 => {
+                AdvanceBy(length * (1.0 + jitter.length * self.random.next()))
+            }
+            RotateBy(angle) if jitter.angle != 0.0 => {
+                RotateBy(angle + jitter.angle * self.random.next())
+            }
+            PitchBy(angle) if jitter.angle != 0.0 => {
+                PitchBy(angle + jitter.angle * self.random.next())
+            }
+            RollBy(angle) if jitter.angle != 0.0 => {
+                RollBy(angle + jitter.angle * self.random.next())
+            }
+            ref command => command.clone(),
+        }
+    }
+
+    /// Rotate the (a, b) orthonormal pair by the given angle in degrees, 'a'
+    /// turning toward 'b'.
+    fn rotate(a: Vector3, b: Vector3, angle: f32) -> (Vector3, Vector3) {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        (a * cos + b * sin, b * cos - a * sin)
+    }
+
+    /// Bend the turtle heading toward the given direction.
+    fn bend(state: &mut TurtleState, direction: &Vector3, susceptibility: f32) {
+        let axis = state.heading.cross(direction);
+        let angle = susceptibility * axis.norm();
+        if angle == 0.0 {
+            return;
+        }
+        let axis = axis.normalized();
+        state.heading = state.heading.rotated(&axis, angle).normalized();
+        state.left = state.left.rotated(&axis, angle).normalized();
+        state.up = state.heading.cross(&state.left);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use rules::HashMapRules;
+    use state::{LSystem, new_rules_value};
+    use interpret::TurtleCommand::*;
+    use super::*;
+
+    fn assert_near(a: Vector3, b: Vector3) {
+        assert!((a - b).norm() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn turtle_2d() {
+        let mut turtle = Turtle::new(TurtleConfig::default());
+        let segments = turtle.execute(&[AdvanceBy(1.0),
+                                        PushState,
+                                        RotateBy(90.0),
+                                        AdvanceBy(2.0),
+                                        PopState,
+                                        RotateBy(-90.0),
+                                        AdvanceBy(1.0),
+                                        None]);
+        assert_eq!(segments.len(), 3);
+        assert_near(segments[0].to, Vector3::new(0.0, 1.0, 0.0));
+        assert_near(segments[1].to, Vector3::new(-2.0, 1.0, 0.0));
+        assert_near(segments[2].from, Vector3::new(0.0, 1.0, 0.0));
+        assert_near(segments[2].to, Vector3::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn turtle_3d() {
+        let mut turtle = Turtle::new(TurtleConfig::default());
+        let segments = turtle.execute(&[PitchBy(90.0), AdvanceBy(1.0), RollBy(90.0),
+                                        RotateBy(90.0), AdvanceBy(1.0)]);
+        assert_near(segments[0].to, Vector3::new(0.0, 0.0, -1.0));
+        assert_near(segments[1].to, Vector3::new(0.0, -1.0, -1.0));
+        let state = turtle.state();
+        assert_near(state.heading.cross(&state.left), state.up);
+    }
+
+    #[test]
+    fn turtle_tropism() {
+        let config = TurtleConfig {
+            tropism: Some(Tropism {
+                direction: Vector3::new(0.0, -1.0, 0.0),
+                susceptibility: 0.2,
+            }),
+            ..TurtleConfig::default()
+        };
+        let mut turtle = Turtle::new(config);
+        turtle.execute(&[RotateBy(-90.0), AdvanceBy(1.0)]);
+        assert_near(turtle.state().heading,
+                    Vector3::new(0.2f32.cos(), -0.2f32.sin(), 0.0));
+
+        // tropism has no effect when heading toward its direction
+        turtle.execute(&[RotateBy(180.0), AdvanceBy(1.0)]);
+        assert_near(turtle.state().heading, Vector3::new(0.0, -1.0, 0.0));
+
+        // per-symbol override
+        let mut rules = HashMapRules::new();
+        rules.set_str('F', "F", AdvanceBy(1.0));
+        rules.set_str('S', "S", AdvanceBy(1.0));
+        rules.set_str('-', "-", RotateBy(-90.0));
+        rules.set_tropism('S', 0.0);
+        let lsystem = LSystem::new_with_char("-SSF", new_rules_value(rules));
+        let segments = turtle.execute_lsystem(&lsystem).ok().unwrap();
+        assert_near(segments[1].to, Vector3::new(2.0, 0.0, 0.0));
+        assert_near(turtle.state().heading,
+                    Vector3::new(0.2f32.cos(), -0.2f32.sin(), 0.0));
+    }
+
+    fn jittered_segments(seed: u64, rules: HashMapRules<char>) -> Vec<Segment> {
+        let config = TurtleConfig {
+            jitter: Some(Jitter {
+                angle: 10.0,
+                length: 0.5,
+            }),
+            seed: seed,
+            ..TurtleConfig::default()
+        };
+        let lsystem = LSystem::new_with_char("F+F+F+F", new_rules_value(rules));
+        Turtle::new(config).execute_lsystem(&lsystem).ok().unwrap()
+    }
+
+    #[test]
+    fn turtle_jitter() {
+        let mut rules = HashMapRules::new();
+        rules.set_str('F', "F", AdvanceBy(1.0));
+        rules.set_str('+', "+", RotateBy(90.0));
+
+        let segments = jittered_segments(42, rules.clone());
+        assert_eq!(segments, jittered_segments(42, rules.clone()));
+        assert!(segments != jittered_segments(43, rules.clone()));
+        for segment in &segments {
+            let length = (segment.to - segment.from).norm();
+            assert!(length >= 0.5 && length <= 1.5);
+        }
+
+        // per-symbol override
+        rules.set_jitter('F',
+                         Jitter {
+                             angle: 0.0,
+                             length: 0.0,
+                         });
+        rules.set_jitter('+',
+                         Jitter {
+                             angle: 0.0,
+                             length: 0.0,
+                         });
+        let segments = jittered_segments(42, rules);
+        assert_near(segments[3].to, Vector3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn turtle_growth() {
+        let mut rules = HashMapRules::new();
+        rules.set_str('F', "F+F", AdvanceBy(1.0));
+        rules.set_str('+', "+", RotateBy(90.0));
+        let lsystem = LSystem::new_with_char("F", new_rules_value(rules));
+        let mut turtle = Turtle::new(TurtleConfig::default());
+
+        assert_eq!(turtle.execute_growth(&lsystem, 0.0),
+                   turtle.execute_lsystem(&lsystem));
+        // the first segment shrinks while the new ones grow at a right angle
+        let segments = turtle.execute_growth(&lsystem, 0.5).ok().unwrap();
+        assert_eq!(segments.len(), 3);
+        assert_near(segments[0].to, Vector3::new(0.0, 0.5, 0.0));
+        assert_near(segments[2].to, Vector3::new(-0.5, 1.0, 0.0));
+        assert_near(turtle.state().heading, Vector3::new(-0.5f32.sqrt(), 0.5f32.sqrt(), 0.0));
+        let segments = turtle.execute_growth(&lsystem, 1.0).ok().unwrap();
+        assert_near(segments[1].to, Vector3::new(-1.0, 1.0, 0.0));
+    }
+}
@@ -1,21 +1,27 @@
-use std::thread;
-use std::time::Duration;
-use std::sync::Arc;
-use std::sync::mpsc::{channel, Sender, Receiver};
+use std::collections::HashMap;
+use std::fmt;
+use std::thread::{self, JoinHandle};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Sender, SyncSender, Receiver};
 use std::cell::RefCell;
 
 use rules::LRules;
 use process::LProcessor;
 use state::{LSystem, RulesValue};
-use interpret::{LInterpreter, TurtleCommand};
+use interpret::{LInterpreter, TurtleCommand, interpret_growth};
 
 /// Common messages from the viewer application to the worker thread.
+/// Except 'ListSessions', the worker configuration ones and 'Terminate', they
+/// apply to the session of their request (see 'Request').
 pub enum MessageFromViewer<S>
     where S: Clone + Eq
 {
-    /// Create a new 'LSystem' from the given axiom and 'LRules', and wait for
-    /// the 'LoadingFinished' message.
-    /// If no L-System is loaded, the other commands will do nothing.
+    /// Create a new 'LSystem' from the given axiom and 'LRules', replacing
+    /// the one of the session if any, and wait for the 'LoadingFinished'
+    /// message.
+    /// If no L-System is loaded in the session, the other commands will do
+    /// nothing but return an error.
     LoadLSystem(Vec<S>, Box<LRules<S> + Send + Sync>),
     /// Reset the L-System to its inital state and wait for the 'ResettingFinised'
     /// confirmation.
@@ -24,6 +30,33 @@ pub enum MessageFromViewer<S>
     Iterate,
     /// Interpret the current L-System state and wait for the 'InterpretationFinished' result.
     Interpret,
+    /// Interpret the growth of the current L-System state toward its next
+    /// generation at the given time in [0, 1] (see
+    /// 'interpret::for_each_growth_interpretation'), and wait for the
+    /// 'InterpretationFinished' result.
+    InterpretGrowth(f32),
+    /// Interpret the current L-System state by batches of at most the given
+    /// number of commands, and wait for the 'InterpretationStream' result
+    /// delivering them as they are produced.
+    /// The batches are produced by a dedicated thread from a snapshot of the
+    /// L-System, so that the worker keeps serving the other requests : a
+    /// stream neither drained nor dropped only holds its own thread.
+    InterpretStream(usize),
+    /// Get the names of the sessions, and wait for the 'SessionList' result.
+    ListSessions,
+    /// Drop the session and its L-System, and wait for the 'SessionDropped'
+    /// confirmation.
+    DropSession,
+    /// Replace the processor shared by all the sessions, keeping their
+    /// L-Systems, and wait for the 'ProcessorChanged' confirmation.
+    SetProcessor(Box<LProcessor<S> + Send>),
+    /// Change the maximum number of tasks and/or the chunk size of the current
+    /// processor (see 'LProcessor::configure'), and wait for the
+    /// 'ProcessorChanged' confirmation.
+    ConfigureProcessor(Option<usize>, Option<usize>),
+    /// Replace the interpreter shared by all the sessions, keeping their
+    /// L-Systems, and wait for the 'InterpreterChanged' confirmation.
+    SetInterpreter(Box<LInterpreter<S> + Send>),
     /// Terminate the worker thread.
     Terminate,
 }
@@ -33,43 +66,231 @@ pub enum MessageFromViewer<S>
 pub enum MessageToViewer {
     LoadingFinished,
     ResettingFinised,
-    /// Confirm that the current 'LSystem' was succesfully evolved to its n-th state.
-    IterationFinished(u64),
-    InterpretationFinished(Vec<TurtleCommand>),
+    /// Confirm that the current 'LSystem' was succesfully evolved to its n-th state,
+    /// of the given length.
+    IterationFinished(u64, usize),
+    /// The interpretation result, shared with the worker which keeps it until
+    /// the L-System changes.
+    InterpretationFinished(Arc<Vec<TurtleCommand>>),
+    InterpretationStream(CommandStream),
+    /// The names of the sessions, in alphabetical order.
+    SessionList(Vec<SessionId>),
+    SessionDropped,
+    ProcessorChanged,
+    InterpreterChanged,
     /// Confirm the worker thread termination then end the thread.
     Terminated,
     Error(String),
 }
 
-impl MessageToViewer {
-    /// Return the type of 'MessageToViewer' expected from the associated command,
-    /// ignoring any possible errors.
-    /// If the result carries meaningful data, load it with dummy values.
-    pub fn from_command<S: Clone + Eq>(msg: &MessageFromViewer<S>) -> MessageToViewer {
-        use self::MessageToViewer::*;
-        use self::MessageFromViewer::*;
-        match *msg {
-            LoadLSystem(_, _) => LoadingFinished,
-            ResetLSystem => ResettingFinised,
-            Iterate => IterationFinished(0),
-            Interpret => InterpretationFinished(Vec::new()),
-            Terminate => Terminated,
+/// The number of batches an 'InterpretStream' command produces in advance,
+/// before waiting for them to be received.
+pub const STREAM_BATCHES: usize = 4;
+
+/// A batch of turtle commands, or the error which ended the interpretation.
+pub type CommandBatch = Result<Vec<TurtleCommand>, String>;
+
+/// Batches of turtle commands streamed by the worker thread in answer to an
+/// 'InterpretStream' command, ending with an error if the interpretation
+/// failed. Dropping all the clones of the stream cancels the interpretation.
+#[derive(Clone)]
+pub struct CommandStream(Arc<Mutex<Receiver<CommandBatch>>>);
+
+impl CommandStream {
+    fn new(rx: Receiver<CommandBatch>) -> CommandStream {
+        CommandStream(Arc::new(Mutex::new(rx)))
+    }
+
+    /// Get the next batch, blocking until it is produced, or None at the end of
+    /// the interpretation.
+    pub fn next_batch(&self) -> Option<CommandBatch> {
+        self.0.lock().ok().and_then(|rx| rx.recv().ok())
+    }
+}
+
+impl Iterator for CommandStream {
+    type Item = CommandBatch;
+
+    fn next(&mut self) -> Option<CommandBatch> {
+        self.next_batch()
+    }
+}
+
+impl PartialEq for CommandStream {
+    fn eq(&self, other: &CommandStream) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for CommandStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CommandStream")
+    }
+}
+
+/// Identifier of a request to the worker thread, echoed in its response.
+pub type RequestId = usize;
+
+/// Counter making the request identifiers unique within the process.
+static NEXT_REQUEST_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Name of a worker session, each session hosting its own L-System.
+pub type SessionId = String;
+
+/// The session of the requests created with 'Request::new'.
+pub const DEFAULT_SESSION: &'static str = "default";
+
+/// Command sent to the worker thread, with its identifier and the session it
+/// applies to.
+/// The commands are processed in order, so that several of them can be sent
+/// without waiting for their responses.
+pub struct Request<S>
+    where S: Clone + Eq
+{
+    pub id: RequestId,
+    pub session: SessionId,
+    pub message: MessageFromViewer<S>,
+}
+
+impl<S: Clone + Eq> Request<S> {
+    /// Create a new request for the default session, with a unique identifier.
+    pub fn new(message: MessageFromViewer<S>) -> Request<S> {
+        Request::in_session(DEFAULT_SESSION, message)
+    }
+
+    /// Create a new request for the given session, with a unique identifier.
+    pub fn in_session(session: &str, message: MessageFromViewer<S>) -> Request<S> {
+        Request {
+            id: NEXT_REQUEST_ID.fetch_add(1, Ordering::SeqCst),
+            session: session.to_string(),
+            message: message,
         }
     }
+}
+
+/// Response of the worker thread to the request of the given identifier.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Response {
+    pub id: RequestId,
+    pub session: SessionId,
+    pub message: MessageToViewer,
+}
 
-    /// Return true if the given argument is of the same 'MessageToViewer' type,
-    /// with no considerations of any eventual carried data (see 'PartialEq' for
-    /// equality check).
-    pub fn same_type(&self, other: &MessageToViewer) -> bool {
+/// L-System hosted by a worker session.
+struct Session<'a, S: Clone + Eq> {
+    axiom: Vec<S>,
+    rules: RulesValue<'a, S>,
+    /// The current L-System, shared with the streams interpreting it.
+    lsystem: Arc<LSystem<'a, S>>,
+    /// The interpretation of the current state, once requested.
+    interpretation: Option<Arc<Vec<TurtleCommand>>>,
+}
+
+impl<'a, S: Clone + Eq> Session<'a, S> {
+    fn new(axiom: Vec<S>, rules: RulesValue<'a, S>) -> Session<'a, S> {
+        Session {
+            lsystem: Arc::new(LSystem::new(axiom.clone(), rules.clone(), None)),
+            axiom: axiom,
+            rules: rules,
+            interpretation: None,
+        }
+    }
+
+    /// Execute the given command on the session's L-System.
+    fn execute(&mut self,
+               message: MessageFromViewer<S>,
+               processor: &RefCell<Box<LProcessor<S> + Send>>,
+               interpreter: &RefCell<Box<LInterpreter<S> + Send>>)
+               -> MessageToViewer {
+        use self::MessageFromViewer::*;
         use self::MessageToViewer::*;
-        match (self, other) {
-            (&LoadingFinished, &LoadingFinished) => true,
-            (&ResettingFinised, &ResettingFinised) => true,
-            (&IterationFinished(_), &IterationFinished(_)) => true,
-            (&InterpretationFinished(_), &InterpretationFinished(_)) => true,
-            (&Terminated, &Terminated) => true,
-            (&Error(_), &Error(_)) => true,
-            _ => false,
+        match message {
+            ResetLSystem => {
+                self.lsystem = Arc::new(LSystem::new(self.axiom.clone(), self.rules.clone(), None));
+                self.interpretation = None;
+                ResettingFinised
+            }
+            Iterate => {
+                // on failure, the last good generation is kept
+                match processor.borrow_mut().iterate(&self.lsystem) {
+                    Ok(v) => {
+                        let response = IterationFinished(v.iteration(), v.state().len());
+                        self.lsystem = Arc::new(v);
+                        self.interpretation = None;
+                        response
+                    }
+                    Err(why) => Error(why),
+                }
+            }
+            Interpret => {
+                match self.interpret(interpreter) {
+                    Ok(commands) => InterpretationFinished(commands),
+                    Err(why) => Error(why),
+                }
+            }
+            InterpretGrowth(t) => {
+                match interpret_growth(&self.lsystem, t) {
+                    Ok(v) => InterpretationFinished(Arc::new(v)),
+                    Err(why) => Error(why),
+                }
+            }
+            _ => Error(format!("not a session command")),
+        }
+    }
+
+    /// Get the interpretation of the current state, kept until it changes.
+    fn interpret(&mut self,
+                 interpreter: &RefCell<Box<LInterpreter<S> + Send>>)
+                 -> Result<Arc<Vec<TurtleCommand>>, String> {
+        if let Some(ref commands) = self.interpretation {
+            return Ok(commands.clone());
+        }
+        let commands = Arc::new(try!(interpreter.borrow_mut().interpret(&self.lsystem)));
+        self.interpretation = Some(commands.clone());
+        Ok(commands)
+    }
+}
+
+impl<S: 'static + Clone + Eq + Send + Sync> Session<'static, S> {
+    /// Send the interpretation of the current state by batches of at most the
+    /// given size to the given channel, from a new thread ending once they
+    /// are all sent or the receiver is dropped.
+    /// Without a kept interpretation, the batches are produced on the fly by a
+    /// duplicate of the interpreter if possible (see 'LInterpreter::duplicate'),
+    /// or split from the interpretation otherwise.
+    fn stream(&mut self,
+              batch_size: usize,
+              batch_tx: SyncSender<CommandBatch>,
+              interpreter: &RefCell<Box<LInterpreter<S> + Send>>) {
+        let batch_size = batch_size.max(1);
+        let duplicate = match self.interpretation {
+            Some(_) => None,
+            None => interpreter.borrow().duplicate(),
+        };
+        if let Some(mut duplicate) = duplicate {
+            let lsystem = self.lsystem.clone();
+            thread::spawn(move || {
+                let result = duplicate.interpret_batches(&lsystem, batch_size, &mut |batch| {
+                    batch_tx.send(Ok(batch)).is_ok()
+                });
+                if let Err(why) = result {
+                    let _ = batch_tx.send(Err(why));
+                }
+            });
+            return;
+        }
+        match self.interpret(interpreter) {
+            Ok(commands) => {
+                thread::spawn(move || for batch in commands.chunks(batch_size) {
+                    if batch_tx.send(Ok(batch.to_vec())).is_err() {
+                        return;
+                    }
+                });
+            }
+            Err(why) => {
+                // the channel is not full yet
+                let _ = batch_tx.send(Err(why));
+            }
         }
     }
 }
@@ -78,84 +299,396 @@ impl MessageToViewer {
 /// and return the needed communication channels.
 /// These allow, in association with a front-end GUI / CLI, to offer a non-blocking
 /// L-System viewer application.
-pub fn start_worker<S: 'static + Clone + Eq + Send>
+pub fn start_worker<S: 'static + Clone + Eq + Send + Sync>
     (processor: RefCell<Box<LProcessor<S> + Send>>,
      interpreter: RefCell<Box<LInterpreter<S> + Send>>)
-     -> (Sender<MessageFromViewer<S>>, Receiver<MessageToViewer>) {
-    let (tx, rx_ui) = channel::<MessageToViewer>();
-    let (tx_ui, rx) = channel::<MessageFromViewer<S>>();
+     -> (Sender<Request<S>>, Receiver<Response>) {
+    let (tx, rx, _) = spawn_worker(processor, interpreter);
+    (tx, rx)
+}
+
+/// Start the worker thread like 'start_worker', also returning its handle so
+/// that its termination can be waited for (see 'shutdown_worker').
+pub fn spawn_worker<S: 'static + Clone + Eq + Send + Sync>
+    (processor: RefCell<Box<LProcessor<S> + Send>>,
+     interpreter: RefCell<Box<LInterpreter<S> + Send>>)
+     -> (Sender<Request<S>>, Receiver<Response>, JoinHandle<()>) {
+    let (tx, rx_ui) = channel::<Response>();
+    let (tx_ui, rx) = channel::<Request<S>>();
 
-    thread::spawn(move || { worker_loop(tx, rx, processor, interpreter); });
+    let handle = thread::spawn(move || { worker_loop(tx, rx, processor, interpreter); });
 
-    (tx_ui, rx_ui)
+    (tx_ui, rx_ui, handle)
 }
 
-/// Worker running function, to be executed in its own thread.
-fn worker_loop<S: Clone + Eq>(tx: Sender<MessageToViewer>,
-                              rx: Receiver<MessageFromViewer<S>>,
-                              processor: RefCell<Box<LProcessor<S> + Send>>,
-                              interpreter: RefCell<Box<LInterpreter<S> + Send>>) {
-    use self::MessageFromViewer::*;
-    use self::MessageToViewer::*;
+/// Ask the worker thread of the given handle to terminate and wait for it,
+/// returning an error if it did not end normally.
+/// NB : the worker thread also terminates when the UI drops its channels.
+pub fn shutdown_worker<S: Clone + Eq>(tx: &Sender<Request<S>>,
+                                      handle: JoinHandle<()>)
+                                      -> Result<(), String> {
+    // the worker may have already stopped
+    let _ = tx.send(Request::new(MessageFromViewer::Terminate));
+    handle.join().map_err(|_| format!("the worker thread has panicked"))
+}
 
-    let sleep_time = Duration::from_millis(25);
+fn no_session(session: &str) -> MessageToViewer {
+    MessageToViewer::Error(format!("no LSystem loaded in session '{}'", session))
+}
 
-    let mut axiom: Option<Vec<S>> = None;
-    let mut rules: Option<RulesValue<S>> = None;
-    let mut lsystem: Option<LSystem<S>> = None;
+/// Worker running function, to be executed in its own thread, blocking until
+/// the next request.
+/// Each session hosts its own L-System, all of them sharing the given processor
+/// and interpreter, which can be replaced or reconfigured at any time.
+/// A failed command leaves the current L-System unchanged and is reported with
+/// an 'Error' message ; the function returns when asked to terminate or when
+/// the UI has dropped any of its channels.
+fn worker_loop<S>(tx: Sender<Response>,
+                  rx: Receiver<Request<S>>,
+                  processor: RefCell<Box<LProcessor<S> + Send>>,
+                  interpreter: RefCell<Box<LInterpreter<S> + Send>>)
+    where S: 'static + Clone + Eq + Send + Sync
+{
+    use self::MessageFromViewer::*;
+    use self::MessageToViewer::*;
 
-    'main: loop {
-        match rx.try_recv() {
-            Ok(message_from_ui) => {
-                match message_from_ui {
-                    LoadLSystem(new_axiom, new_rules) => {
-                        axiom = Some(new_axiom);
-                        rules = Some(Arc::new(new_rules));
-                        lsystem = Some(LSystem::<S>::new(axiom.clone().unwrap(),
-                                                         rules.clone().unwrap(),
-                                                         None));
-                        tx.send(LoadingFinished).unwrap();
+    let mut sessions: HashMap<SessionId, Session<'static, S>> = HashMap::new();
 
+    // ends when the UI has dropped its sender
+    while let Ok(request) = rx.recv() {
+        let session = request.session;
+        let response = match request.message {
+            LoadLSystem(axiom, rules) => {
+                sessions.insert(session.clone(), Session::new(axiom, Arc::new(rules)));
+                LoadingFinished
+            }
+            ListSessions => {
+                let mut names: Vec<SessionId> = sessions.keys().cloned().collect();
+                names.sort();
+                SessionList(names)
+            }
+            Terminate => {
+                let _ = tx.send(Response {
+                    id: request.id,
+                    session: session,
+                    message: Terminated,
+                });
+                return;
+            }
+            DropSession => {
+                match sessions.remove(&session) {
+                    Some(_) => SessionDropped,
+                    None => no_session(&session),
+                }
+            }
+            SetProcessor(new_processor) => {
+                *processor.borrow_mut() = new_processor;
+                ProcessorChanged
+            }
+            ConfigureProcessor(max_tasks, chunk_size) => {
+                match processor.borrow_mut().configure(max_tasks, chunk_size) {
+                    Ok(()) => ProcessorChanged,
+                    Err(why) => Error(why),
+                }
+            }
+            SetInterpreter(new_interpreter) => {
+                *interpreter.borrow_mut() = new_interpreter;
+                // the cached interpretations may differ with the new interpreter
+                for current in sessions.values_mut() {
+                    current.interpretation = None;
+                }
+                InterpreterChanged
+            }
+            InterpretStream(batch_size) => {
+                match sessions.get_mut(&session) {
+                    Some(current) => {
+                        let (batch_tx, batch_rx) = sync_channel(STREAM_BATCHES);
+                        current.stream(batch_size, batch_tx, &interpreter);
+                        InterpretationStream(CommandStream::new(batch_rx))
                     }
-                    ResetLSystem if lsystem.is_some() => {
-                        lsystem = Some(LSystem::<S>::new(axiom.clone().unwrap(),
-                                                         rules.clone().unwrap(),
-                                                         None));
-                        tx.send(ResettingFinised).unwrap();
-                    }
-                    Iterate if lsystem.is_some() => {
-                        lsystem = match processor.borrow_mut().iterate(lsystem.as_ref().unwrap()) {
-                            Ok(v) => {
-                                tx.send(IterationFinished(v.iteration())).unwrap();
-                                Some(v)
-                            }
-                            Err(why) => {
-                                tx.send(Error(why)).unwrap();
-                                None
-                            }
-                        };
-                        println!("> state len = {:?}\n",
-                                 lsystem.as_ref().unwrap().state().len());
-                    }
-                    Interpret if lsystem.is_some() => {
-                        match interpreter
-                                  .borrow_mut()
-                                  .interpret(lsystem.as_ref().unwrap()) {
-                            Ok(v) => tx.send(InterpretationFinished(v)).unwrap(),
-                            Err(why) => tx.send(Error(why)).unwrap(),
-                        }
-                    }
-                    Terminate => {
-                        tx.send(Terminated).unwrap();
-                        break 'main;
-                    }
-                    _ => tx.send(Error(format!("no LSystem loaded"))).unwrap(),
+                    None => no_session(&session),
                 }
             }
-            _ => (),
+            message => {
+                match sessions.get_mut(&session) {
+                    Some(current) => current.execute(message, &processor, &interpreter),
+                    None => no_session(&session),
+                }
+            }
+        };
+        let response = Response {
+            id: request.id,
+            session: session,
+            message: response,
+        };
+        if tx.send(response).is_err() {
+            // the UI has dropped its receiver
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use rules::HashMapRules;
+    use process::{LProcessor, SimpleProcessor, ChunksProcessor};
+    use interpret::{LInterpreter, SimpleInterpreter, TurtleCommand};
+    use super::*;
+    use super::MessageFromViewer::*;
+    use super::MessageToViewer::*;
+
+    /// Get the message of the given 'Error' response.
+    fn error(message: MessageToViewer) -> String {
+        match message {
+            Error(why) => why,
+            message => panic!("unexpected response : {:?}", message),
+        }
+    }
+
+    fn spawn() -> (Sender<Request<char>>, Receiver<Response>, JoinHandle<()>) {
+        let processor: Box<LProcessor<char> + Send> = Box::new(SimpleProcessor);
+        let interpreter: Box<LInterpreter<char> + Send> = Box::new(SimpleInterpreter);
+        spawn_worker(RefCell::new(processor), RefCell::new(interpreter))
+    }
+
+    #[test]
+    fn worker_failures() {
+        let (tx, rx, handle) = spawn();
+        let command = |msg| {
+            let request = Request::new(msg);
+            let id = request.id;
+            tx.send(request).unwrap();
+            let response = rx.recv().unwrap();
+            assert_eq!(response.id, id);
+            response.message
+        };
+        assert_eq!(command(Iterate),
+                   Error(format!("no LSystem loaded in session 'default'")));
+
+        // the second iteration fails on a self-recursive decomposition
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::AdvanceBy(1.0));
+        rules.set_str('B', "C", TurtleCommand::None);
+        rules.set_decomposition_str('C', "CC");
+        assert_eq!(command(LoadLSystem(vec!['A'], Box::new(rules))), LoadingFinished);
+        assert_eq!(command(Iterate), IterationFinished(1, 2));
+        assert!(error(command(Iterate)).contains("decomposition depth"));
+        assert_eq!(command(Interpret),
+                   InterpretationFinished(Arc::new(vec![TurtleCommand::AdvanceBy(1.0)])));
+        assert_eq!(command(ResetLSystem), ResettingFinised);
+        assert_eq!(command(Iterate), IterationFinished(1, 2));
+        assert!(shutdown_worker(&tx, handle).is_ok());
+
+        // dropping the channels stops the worker
+        let (tx, rx, handle) = spawn();
+        drop(rx);
+        tx.send(Request::new(ResetLSystem)).unwrap();
+        assert!(handle.join().is_ok());
+    }
+
+    #[test]
+    fn worker_pipelining() {
+        let (tx, rx, handle) = spawn();
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::None);
+        rules.set_str('B', "A", TurtleCommand::None);
+        let requests = vec![Request::new(LoadLSystem(vec!['A'], Box::new(rules))),
+                            Request::new(Iterate),
+                            Request::new(Iterate),
+                            Request::new(Iterate)];
+        let ids: Vec<RequestId> = requests.iter().map(|r| r.id).collect();
+        for request in requests {
+            tx.send(request).unwrap();
+        }
+        let responses: Vec<Response> = ids.iter().map(|_| rx.recv().unwrap()).collect();
+        assert_eq!(responses.iter().map(|r| r.id).collect::<Vec<_>>(), ids);
+        assert_eq!(responses[3].message, IterationFinished(3, 5));
+        assert!(ids[1] != ids[2]);
+
+        drop(tx);
+        assert!(handle.join().is_ok());
+    }
+
+    #[test]
+    fn worker_streaming() {
+        let (tx, rx, handle) = spawn();
+        let command = |msg| {
+            tx.send(Request::new(msg)).unwrap();
+            rx.recv().unwrap().message
+        };
+        let mut rules = HashMapRules::new();
+        rules.set_str('F', "FF", TurtleCommand::AdvanceBy(1.0));
+        command(LoadLSystem(vec!['F'], Box::new(rules)));
+        for _ in 0..10 {
+            command(Iterate);
+        }
+
+        // the interpretation is shared, and kept until the next iteration
+        let commands = match command(Interpret) {
+            InterpretationFinished(commands) => commands,
+            message => panic!("unexpected response : {:?}", message),
+        };
+        assert_eq!(commands.len(), 1024);
+        match command(Interpret) {
+            InterpretationFinished(again) => assert!(Arc::ptr_eq(&commands, &again)),
+            message => panic!("unexpected response : {:?}", message),
+        }
+
+        let stream = match command(InterpretStream(100)) {
+            InterpretationStream(stream) => stream,
+            message => panic!("unexpected response : {:?}", message),
+        };
+        let batches: Vec<Vec<TurtleCommand>> = stream.map(|batch| batch.ok().unwrap()).collect();
+        assert_eq!(batches.len(), 11);
+        assert_eq!(&batches.concat(), &*commands);
+
+        // a dropped stream cancels the interpretation
+        match command(InterpretStream(1)) {
+            InterpretationStream(stream) => {
+                assert!(stream.next_batch().is_some());
+                drop(stream);
+            }
+            message => panic!("unexpected response : {:?}", message),
+        }
+        assert_eq!(command(Iterate), IterationFinished(11, 2048));
+        assert!(shutdown_worker(&tx, handle).is_ok());
+    }
+
+    /// Interpreter which cannot be duplicated.
+    struct BufferedInterpreter;
+
+    impl LInterpreter<char> for BufferedInterpreter {
+        fn interpret(&mut self, lsystem: &LSystem<char>) -> Result<Vec<TurtleCommand>, String> {
+            SimpleInterpreter.interpret(lsystem)
+        }
+    }
+
+    #[test]
+    fn worker_streams() {
+        let (tx, rx, handle) = spawn();
+        let command = |session, msg| {
+            tx.send(Request::in_session(session, msg)).unwrap();
+            rx.recv().unwrap().message
+        };
+        let stream = |session| {
+            match command(session, InterpretStream(1)) {
+                InterpretationStream(stream) => stream,
+                message => panic!("unexpected response : {:?}", message),
+            }
+        };
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::AdvanceBy(1.0));
+        rules.set_str('B', "A", TurtleCommand::AdvanceBy(1.0));
+        for session in &["a", "b"] {
+            command(session, LoadLSystem(vec!['A'], Box::new(rules.clone())));
+            for _ in 0..4 {
+                command(session, Iterate);
+            }
+        }
+
+        // other sessions are served while streams are not drained, whether
+        // the interpreter can be duplicated or not
+        let first = stream("a");
+        assert_eq!(command("b", Iterate), IterationFinished(5, 13));
+        assert_eq!(command("b", SetInterpreter(Box::new(BufferedInterpreter))),
+                   InterpreterChanged);
+        let second = stream("b");
+        assert_eq!(command("a", Iterate), IterationFinished(5, 13));
+        match command("b", Interpret) {
+            InterpretationFinished(commands) => assert_eq!(commands.len(), 13),
+            message => panic!("unexpected response : {:?}", message),
+        }
+
+        // each stream interprets the generation it was requested for
+        let batches: Vec<Vec<TurtleCommand>> = first.map(|batch| batch.ok().unwrap()).collect();
+        assert_eq!(batches.concat().len(), 8);
+        let batches: Vec<Vec<TurtleCommand>> = second.map(|batch| batch.ok().unwrap()).collect();
+        assert_eq!(batches.concat().len(), 13);
+        assert!(shutdown_worker(&tx, handle).is_ok());
+    }
+
+    #[test]
+    fn worker_reconfiguration() {
+        let (tx, rx, handle) = spawn();
+        let command = |msg| {
+            tx.send(Request::new(msg)).unwrap();
+            rx.recv().unwrap().message
+        };
+        let mut rules = HashMapRules::new();
+        rules.set_str('A', "AB", TurtleCommand::AdvanceBy(1.0));
+        rules.set_str('B', "A", TurtleCommand::None);
+        command(LoadLSystem(vec!['A'], Box::new(rules)));
+        assert_eq!(command(Iterate), IterationFinished(1, 2));
+        assert_eq!(error(command(ConfigureProcessor(Some(2), None))),
+                   "this processor has no parameters");
+
+        // the L-System is kept when the processor changes
+        let chunks = ChunksProcessor::new(2, 3).ok().unwrap();
+        assert_eq!(command(SetProcessor(Box::new(chunks))), ProcessorChanged);
+        assert_eq!(command(Iterate), IterationFinished(2, 3));
+        assert_eq!(command(ConfigureProcessor(Some(3), Some(2))), ProcessorChanged);
+        let invalid = error(command(ConfigureProcessor(None, Some(0))));
+        assert!(invalid.contains("invalid chunks size"));
+        let invalid = error(command(ConfigureProcessor(Some(0), None)));
+        assert!(invalid.contains("invalid maximum tasks"));
+        for _ in 0..8 {
+            command(Iterate);
         }
-        // avoid over-charging the CPU thread
-        // when waiting for a command
-        thread::sleep(sleep_time);
+        assert_eq!(command(Iterate), IterationFinished(11, 233));
+
+        // the cached interpretation is dropped with the interpreter
+        let commands = match command(Interpret) {
+            InterpretationFinished(commands) => commands,
+            message => panic!("unexpected response : {:?}", message),
+        };
+        assert_eq!(command(SetInterpreter(Box::new(SimpleInterpreter))), InterpreterChanged);
+        match command(Interpret) {
+            InterpretationFinished(again) => {
+                assert!(!Arc::ptr_eq(&commands, &again));
+                assert_eq!(commands, again);
+            }
+            message => panic!("unexpected response : {:?}", message),
+        }
+        assert!(shutdown_worker(&tx, handle).is_ok());
+    }
+
+    #[test]
+    fn worker_sessions() {
+        let (tx, rx, handle) = spawn();
+        let command = |session, msg| {
+            tx.send(Request::in_session(session, msg)).unwrap();
+            let response = rx.recv().unwrap();
+            assert_eq!(response.session, session);
+            response.message
+        };
+        let mut algae = HashMapRules::new();
+        algae.set_str('A', "AB", TurtleCommand::None);
+        algae.set_str('B', "A", TurtleCommand::None);
+        let mut koch = HashMapRules::new();
+        koch.set_str('F', "F+F-F-F+F", TurtleCommand::None);
+
+        assert_eq!(command("koch", LoadLSystem(vec!['F'], Box::new(koch))),
+                   LoadingFinished);
+        assert_eq!(command("algae", LoadLSystem(vec!['A'], Box::new(algae))),
+                   LoadingFinished);
+        assert_eq!(command("algae", Iterate), IterationFinished(1, 2));
+        assert_eq!(command("koch", Iterate), IterationFinished(1, 9));
+        assert_eq!(command("algae", Iterate), IterationFinished(2, 3));
+        assert_eq!(command("koch", ResetLSystem), ResettingFinised);
+        assert_eq!(command("algae", Iterate), IterationFinished(3, 5));
+        assert_eq!(command("", ListSessions),
+                   SessionList(vec!["algae".to_string(), "koch".to_string()]));
+
+        assert_eq!(command("algae", DropSession), SessionDropped);
+        assert_eq!(command("algae", Iterate),
+                   Error(format!("no LSystem loaded in session 'algae'")));
+        assert_eq!(command("algae", DropSession),
+                   Error(format!("no LSystem loaded in session 'algae'")));
+        assert_eq!(command("koch", Iterate), IterationFinished(1, 9));
+        assert_eq!(command("", ListSessions), SessionList(vec!["koch".to_string()]));
+
+        assert!(shutdown_worker(&tx, handle).is_ok());
     }
 }